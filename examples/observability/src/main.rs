@@ -0,0 +1,55 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use mq::{Consumer, Context, Job, JobResult, MetricsServer, Producer, Worker};
+use mq_surreal::{SurrealJobProcessor, SurrealProducer};
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // with the opentelemetry feature, jobs are processed in a `job` span continuing the trace of
+    // the publisher once an OpenTelemetry tracer and propagator are installed
+    tracing_subscriber::fmt().init();
+
+    // connect to an in-memory surrealdb
+    let db = Arc::new(surrealdb::engine::any::connect("mem://").await?);
+    db.use_ns("test").use_db("test").await?;
+
+    let table = "queue";
+    mq_surreal::migrate(&db, table).await?;
+
+    let producer = Arc::new(SurrealProducer::new(db.clone(), table));
+    for index in 0..10 {
+        producer
+            .publish(Job::new("resize-image", json!({ "index": index })))
+            .await?;
+    }
+
+    let cancellation_token = CancellationToken::new();
+    let worker = Worker::new(
+        Consumer::new().register(("resize-image", |_: Context| async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(JobResult::CompleteWithSuccess)
+        })),
+    )
+    .with_producer(producer)
+    // sample the mq_queue_depth gauges every 5 seconds
+    .with_queue_depth_interval(Some(5000))
+    .with_cancellation_token(cancellation_token.clone());
+
+    // serve prometheus metrics and health probes on http://127.0.0.1:9464
+    let metrics_server = MetricsServer::install(([127, 0, 0, 1], 9464))?
+        .with_worker(worker.health().clone())
+        .with_cancellation_token(cancellation_token.clone());
+    tokio::spawn(metrics_server.run());
+
+    tokio::spawn(async move {
+        tokio::signal::ctrl_c().await.ok();
+        cancellation_token.cancel();
+    });
+
+    worker.run(SurrealJobProcessor::new(db, table)).await?;
+
+    Ok(())
+}
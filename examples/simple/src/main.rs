@@ -38,27 +38,8 @@ async fn main() -> Result<()> {
 
     let table = "queue";
 
-    // create table schema for mq
-    db.query(format!(
-        r#"
-DEFINE TABLE IF NOT EXISTS {table} SCHEMAFULL;
-DEFINE FIELD IF NOT EXISTS created_at     ON {table} TYPE datetime;
-DEFINE FIELD IF NOT EXISTS updated_at     ON {table} TYPE datetime;
-DEFINE FIELD IF NOT EXISTS scheduled_at   ON {table} TYPE datetime;
-DEFINE FIELD IF NOT EXISTS locked_at      ON {table} TYPE option<datetime>;
-DEFINE FIELD IF NOT EXISTS queue          ON {table} TYPE string;
-DEFINE FIELD IF NOT EXISTS kind           ON {table} TYPE string;
-DEFINE FIELD IF NOT EXISTS max_attempts   ON {table} TYPE number;
-DEFINE FIELD IF NOT EXISTS attempts       ON {table} TYPE number;
-DEFINE FIELD IF NOT EXISTS priority       ON {table} TYPE number;
-DEFINE FIELD IF NOT EXISTS unique_key     ON {table} TYPE option<string>;
-DEFINE FIELD IF NOT EXISTS lease_time     ON {table} TYPE number;
-DEFINE FIELD IF NOT EXISTS payload        ON {table} TYPE object FLEXIBLE;
-DEFINE FIELD IF NOT EXISTS error_reason   ON {table} TYPE option<object> FLEXIBLE;
-    "#
-    ))
-    .await?
-    .check()?;
+    // create or upgrade the tables of mq
+    mq_surreal::migrate(&db, table).await?;
 
     // uncomment the following line to delete all the records in the table to start clean
     // db.query(format!("DELETE {table}")).await?.check()?;
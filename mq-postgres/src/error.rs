@@ -0,0 +1,10 @@
+use mq::Error;
+
+pub(crate) fn convert_sqlx_error(err: sqlx::Error) -> Error {
+    Error::OtherError(Box::new(err))
+}
+
+/// Quote the table name so it can be interpolated in statements.
+pub(crate) fn quote(table: &str) -> String {
+    format!("\"{}\"", table.replace('"', "\"\""))
+}
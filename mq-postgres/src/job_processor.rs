@@ -0,0 +1,146 @@
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use mq::{Error, Job, JobProcessor};
+use serde_json::Value;
+use sqlx::{postgres::PgListener, PgPool};
+use tracing::warn;
+
+use crate::error::{convert_sqlx_error, quote};
+
+pub struct PostgresJobProcessor {
+    pool: PgPool,
+    table: String,
+}
+
+impl PostgresJobProcessor {
+    pub fn new<T: Into<String>>(pool: PgPool, table: T) -> Self {
+        Self {
+            pool,
+            table: table.into(),
+        }
+    }
+
+    async fn delete(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE id=$1 AND queue=$2 AND kind=$3",
+            quote(&self.table)
+        ))
+        .bind(id)
+        .bind(queue)
+        .bind(kind)
+        .execute(&self.pool)
+        .await
+        .map_err(convert_sqlx_error)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl JobProcessor for PostgresJobProcessor {
+    async fn poll_next_job(&self, queues: &[&str]) -> Result<Option<Job>, Error> {
+        let job: Option<Value> = sqlx::query_scalar(&format!(
+            r#"
+            UPDATE {table} AS job
+            SET
+                attempts=attempts+1,
+                locked_at=now(),
+                updated_at=now()
+            WHERE id=(
+                SELECT id FROM {table}
+                WHERE
+                    attempts<max_attempts
+                    AND scheduled_at<=now()
+                    AND (locked_at IS NULL OR locked_at<now()-make_interval(secs => lease_time))
+                    AND queue=ANY($1)
+                ORDER BY priority DESC, updated_at ASC
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING to_jsonb(job)
+            "#,
+            table = quote(&self.table)
+        ))
+        .bind(queues)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(convert_sqlx_error)?;
+
+        Ok(job.map(serde_json::from_value).transpose()?)
+    }
+
+    async fn job_notifications(
+        &self,
+        queues: &[&str],
+    ) -> Result<Option<BoxStream<'static, ()>>, Error> {
+        let mut listener = PgListener::connect_with(&self.pool)
+            .await
+            .map_err(convert_sqlx_error)?;
+        listener
+            .listen(&self.table)
+            .await
+            .map_err(convert_sqlx_error)?;
+
+        let queues: Vec<String> = queues.iter().map(|q| q.to_string()).collect();
+        let notifications = listener.into_stream().filter_map(move |notification| {
+            let notify = match notification {
+                Ok(notification) => queues.iter().any(|q| q == notification.payload()),
+                Err(e) => {
+                    warn!("Failed to receive job notification: {:?}", e);
+                    false
+                }
+            };
+            async move { notify.then_some(()) }
+        });
+
+        Ok(Some(notifications.boxed()))
+    }
+
+    async fn complete_job_with_success(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<(), Error> {
+        self.delete(queue, kind, id).await
+    }
+
+    async fn complete_job_with_cancelled(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        _message: Option<String>,
+    ) -> Result<(), Error> {
+        self.delete(queue, kind, id).await
+    }
+
+    async fn fail_job(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        reason: Value,
+    ) -> Result<(), Error> {
+        sqlx::query(&format!(
+            r#"
+            UPDATE {}
+            SET
+                locked_at=NULL,
+                updated_at=now(),
+                error_reason=$1
+            WHERE id=$2 AND queue=$3 AND kind=$4
+            "#,
+            quote(&self.table)
+        ))
+        .bind(reason)
+        .bind(id)
+        .bind(queue)
+        .bind(kind)
+        .execute(&self.pool)
+        .await
+        .map_err(convert_sqlx_error)?;
+
+        Ok(())
+    }
+}
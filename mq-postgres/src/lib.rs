@@ -0,0 +1,8 @@
+mod error;
+mod job_processor;
+mod migrations;
+mod producer;
+
+pub use job_processor::*;
+pub use migrations::*;
+pub use producer::*;
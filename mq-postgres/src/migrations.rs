@@ -0,0 +1,96 @@
+use mq::Error;
+use sqlx::PgPool;
+
+use crate::error::{convert_sqlx_error, quote};
+
+/// Migrations of the jobs table applied in order. `{table}` is replaced with the quoted table
+/// name, `{name}` with the raw one and `{channel}` with the notification channel as a literal.
+const MIGRATIONS: &[&str] = &[r#"
+    CREATE TABLE {table} (
+        id           TEXT PRIMARY KEY,
+        queue        TEXT NOT NULL,
+        kind         TEXT NOT NULL,
+        payload      JSONB NOT NULL,
+        headers      JSONB NOT NULL DEFAULT '{}',
+        created_at   TIMESTAMPTZ NOT NULL,
+        updated_at   TIMESTAMPTZ NOT NULL,
+        scheduled_at TIMESTAMPTZ NOT NULL,
+        locked_at    TIMESTAMPTZ,
+        attempts     INTEGER NOT NULL,
+        max_attempts INTEGER NOT NULL,
+        priority     SMALLINT NOT NULL,
+        unique_key   TEXT,
+        lease_time   BIGINT NOT NULL,
+        error_reason JSONB
+    );
+
+    CREATE INDEX "{name}_poll" ON {table} (queue, priority DESC, updated_at)
+        WHERE attempts<max_attempts;
+
+    CREATE UNIQUE INDEX "{name}_unique_key" ON {table} (queue, kind, unique_key)
+        WHERE unique_key IS NOT NULL AND attempts<max_attempts;
+
+    CREATE FUNCTION "{name}_notify"() RETURNS trigger AS $$
+    BEGIN
+        PERFORM pg_notify({channel}, NEW.queue);
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql;
+
+    CREATE TRIGGER "{name}_notify" AFTER INSERT ON {table}
+        FOR EACH ROW EXECUTE FUNCTION "{name}_notify"();
+"#];
+
+/// Create or upgrade the jobs table. The applied migrations are tracked per table in
+/// `mq_migrations`.
+///
+/// Inserting a job notifies the channel named after the table with the queue of the job.
+pub async fn migrate(pool: &PgPool, table: &str) -> Result<(), Error> {
+    let mut tx = pool.begin().await.map_err(convert_sqlx_error)?;
+
+    // serializes concurrent migrations
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('mq_migrations'))")
+        .execute(&mut *tx)
+        .await
+        .map_err(convert_sqlx_error)?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS mq_migrations (
+            table_name TEXT PRIMARY KEY,
+            version    INTEGER NOT NULL
+        )",
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(convert_sqlx_error)?;
+
+    let version: Option<i32> =
+        sqlx::query_scalar("SELECT version FROM mq_migrations WHERE table_name=$1")
+            .bind(table)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(convert_sqlx_error)?;
+
+    for migration in MIGRATIONS.iter().skip(version.unwrap_or(0) as usize) {
+        let migration = migration
+            .replace("{table}", &quote(table))
+            .replace("{name}", &table.replace('"', "\"\""))
+            .replace("{channel}", &format!("'{}'", table.replace('\'', "''")));
+        sqlx::raw_sql(&migration)
+            .execute(&mut *tx)
+            .await
+            .map_err(convert_sqlx_error)?;
+    }
+
+    sqlx::query(
+        "INSERT INTO mq_migrations (table_name, version) VALUES ($1, $2)
+        ON CONFLICT (table_name) DO UPDATE SET version=excluded.version",
+    )
+    .bind(table)
+    .bind(MIGRATIONS.len() as i32)
+    .execute(&mut *tx)
+    .await
+    .map_err(convert_sqlx_error)?;
+
+    tx.commit().await.map_err(convert_sqlx_error)
+}
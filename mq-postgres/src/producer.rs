@@ -0,0 +1,219 @@
+use async_trait::async_trait;
+use mq::{Error, Job, Producer, PublishOutcome, QueueDepth, UniqueKeyConflict};
+use sqlx::{PgConnection, PgPool};
+
+use crate::error::{convert_sqlx_error, quote};
+
+/// Whether a worker runs the job of the `job` row and its lease has not expired.
+const LEASED: &str = "COALESCE(job.locked_at>=now()-make_interval(secs => job.lease_time), false)";
+
+pub struct PostgresProducer {
+    pool: PgPool,
+    table: String,
+}
+
+impl PostgresProducer {
+    pub fn new<T: Into<String>>(pool: PgPool, table: T) -> Self {
+        Self {
+            pool,
+            table: table.into(),
+        }
+    }
+
+    /// Publish the jobs with the given connection, e.g. a transaction of the caller, so the jobs
+    /// are only published if the transaction commits. Returns the outcome of each job in order.
+    ///
+    /// ```ignore
+    /// let mut tx = pool.begin().await?;
+    /// // ... update the business tables with the transaction
+    /// let outcomes = producer.publish_with(&mut tx, vec![job]).await?;
+    /// tx.commit().await?;
+    /// ```
+    ///
+    /// The jobs are published one statement at a time, so outside of a transaction a failing job
+    /// does not undo the jobs published before it.
+    pub async fn publish_with(
+        &self,
+        conn: &mut PgConnection,
+        jobs: Vec<Job>,
+    ) -> Result<Vec<PublishOutcome>, Error> {
+        if jobs.iter().any(|job| !job.depends_on().is_empty()) {
+            return Err(Error::NotSupported("depends_on".into()));
+        }
+        // compensations of sagas are chained with depends_on
+        if jobs.iter().any(|job| !job.saga_steps().is_empty()) {
+            return Err(Error::NotSupported("saga_steps".into()));
+        }
+
+        let mut outcomes = Vec::with_capacity(jobs.len());
+        for job in jobs {
+            outcomes.push(
+                self.publish_job(&mut *conn, job.with_trace_context())
+                    .await?,
+            );
+        }
+        Ok(outcomes)
+    }
+
+    async fn publish_job(
+        &self,
+        conn: &mut PgConnection,
+        job: Job,
+    ) -> Result<PublishOutcome, Error> {
+        let on_unique_key_conflict = job.on_unique_key_conflict();
+
+        // The conflicting job is always updated, keeping its columns for skipped jobs and jobs
+        // leased by a worker, so its id is returned. xmax is 0 for inserted rows only.
+        let (id, inserted, leased): (String, bool, bool) = sqlx::query_as(&format!(
+            r#"
+            INSERT INTO {} AS job (
+                id, queue, kind, payload, headers, created_at, updated_at, scheduled_at,
+                locked_at, attempts, max_attempts, priority, unique_key, lease_time, error_reason
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, now(), now(), COALESCE($6, now()),
+                NULL, $7, $8, $9, $10, $11, NULL
+            )
+            ON CONFLICT (queue, kind, unique_key)
+                WHERE unique_key IS NOT NULL AND attempts<max_attempts
+                DO UPDATE SET
+                    payload=CASE
+                        WHEN $12='replace' AND NOT {leased} THEN excluded.payload
+                        ELSE job.payload
+                    END,
+                    scheduled_at=CASE
+                        WHEN $12 IN ('replace', 'reschedule') AND NOT {leased}
+                            THEN excluded.scheduled_at
+                        ELSE job.scheduled_at
+                    END
+            RETURNING id, xmax=0, {leased}
+            "#,
+            quote(&self.table),
+            leased = LEASED,
+        ))
+        .bind(job.id())
+        .bind(job.queue())
+        .bind(job.kind())
+        .bind(job.payload())
+        .bind(serde_json::to_value(job.headers())?)
+        .bind(*job.scheduled_at())
+        .bind(job.attempts() as i32)
+        .bind(job.max_attempts() as i32)
+        .bind(job.priority() as i16)
+        .bind(job.unique_key())
+        .bind(job.lease_time().as_secs() as i64)
+        .bind(on_unique_key_conflict.as_str())
+        .fetch_one(conn)
+        .await
+        .map_err(convert_sqlx_error)?;
+
+        let outcome = match on_unique_key_conflict {
+            _ if inserted => PublishOutcome::Published,
+            _ if leased => PublishOutcome::Duplicate(id),
+            UniqueKeyConflict::Replace | UniqueKeyConflict::Reschedule => {
+                PublishOutcome::Replaced(id)
+            }
+            UniqueKeyConflict::Skip | UniqueKeyConflict::Error => PublishOutcome::Duplicate(id),
+        };
+        outcome.check(on_unique_key_conflict)
+    }
+}
+
+#[async_trait]
+impl Producer for PostgresProducer {
+    async fn publish(&self, job: Job) -> Result<PublishOutcome, Error> {
+        let mut conn = self.pool.acquire().await.map_err(convert_sqlx_error)?;
+        let outcome = self
+            .publish_with(&mut conn, vec![job])
+            .await?
+            .pop()
+            .expect("outcome of the published job");
+
+        Ok(outcome)
+    }
+
+    async fn exists(&self, queue: &str, kind: &str, id: &str) -> Result<bool, Error> {
+        sqlx::query_scalar(&format!(
+            "SELECT EXISTS (SELECT 1 FROM {} WHERE id=$1 AND queue=$2 AND kind=$3)",
+            quote(&self.table)
+        ))
+        .bind(id)
+        .bind(queue)
+        .bind(kind)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(convert_sqlx_error)
+    }
+
+    async fn cancel_by_id(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE id=$1 AND queue=$2 AND kind=$3",
+            quote(&self.table)
+        ))
+        .bind(id)
+        .bind(queue)
+        .bind(kind)
+        .execute(&self.pool)
+        .await
+        .map_err(convert_sqlx_error)?;
+
+        Ok(())
+    }
+
+    async fn cancel_by_unique_key(&self, queue: &str, kind: &str, key: &str) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE queue=$1 AND kind=$2 AND unique_key=$3",
+            quote(&self.table)
+        ))
+        .bind(queue)
+        .bind(kind)
+        .bind(key)
+        .execute(&self.pool)
+        .await
+        .map_err(convert_sqlx_error)?;
+
+        Ok(())
+    }
+
+    async fn publish_many(&self, jobs: Vec<Job>) -> Result<Vec<PublishOutcome>, Error> {
+        let mut tx = self.pool.begin().await.map_err(convert_sqlx_error)?;
+        let outcomes = self.publish_with(&mut tx, jobs).await?;
+        tx.commit().await.map_err(convert_sqlx_error)?;
+        Ok(outcomes)
+    }
+
+    async fn queue_depth(&self, queue: &str) -> Result<QueueDepth, Error> {
+        let (ready, scheduled, running, dead): (i64, i64, i64, i64) = sqlx::query_as(&format!(
+            r#"
+            SELECT
+                count(*) FILTER (
+                    WHERE NOT leased AND attempts<max_attempts AND scheduled_at<=now()
+                ),
+                count(*) FILTER (
+                    WHERE NOT leased AND attempts<max_attempts AND scheduled_at>now()
+                ),
+                count(*) FILTER (WHERE leased),
+                count(*) FILTER (WHERE NOT leased AND attempts>=max_attempts)
+            FROM (
+                SELECT
+                    *,
+                    COALESCE(locked_at>=now()-make_interval(secs => lease_time), false) AS leased
+                FROM {}
+                WHERE queue=$1
+            ) AS job
+            "#,
+            quote(&self.table)
+        ))
+        .bind(queue)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(convert_sqlx_error)?;
+
+        Ok(QueueDepth {
+            ready: ready as u64,
+            scheduled: scheduled as u64,
+            running: running as u64,
+            dead: dead as u64,
+        })
+    }
+}
@@ -0,0 +1,33 @@
+//! Runs against the PostgreSQL database at `DATABASE_URL`:
+//!
+//! ```sh
+//! DATABASE_URL=postgres://localhost/mq cargo test -p mq-postgres -- --ignored
+//! ```
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mq_postgres::{PostgresJobProcessor, PostgresProducer};
+use mq_testsuite::Backend;
+
+async fn backend() -> Backend {
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL to be set");
+    let pool = sqlx::PgPool::connect(&url).await.unwrap();
+
+    // every test uses its own table
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let table = format!("mq_test_{nanos}");
+    mq_postgres::migrate(&pool, &table).await.unwrap();
+
+    Backend::new(
+        PostgresProducer::new(pool.clone(), &table),
+        PostgresJobProcessor::new(pool, &table),
+    )
+}
+
+mq_testsuite::backend_tests!(
+    #[ignore = "needs a PostgreSQL database at DATABASE_URL"]
+    backend
+);
@@ -0,0 +1,28 @@
+use std::{path::Path, sync::Arc};
+
+use mq::Error;
+use redb::Database;
+
+use crate::error::convert_redb_error;
+
+/// Database shared by the producer and the job processor.
+pub type RedbDatabase = Arc<Database>;
+
+/// Open the database, creating the file if it does not exist.
+pub fn open<P: AsRef<Path>>(path: P) -> Result<RedbDatabase, Error> {
+    Ok(Arc::new(
+        Database::create(path).map_err(convert_redb_error)?,
+    ))
+}
+
+/// Run the closure on a blocking thread with the database.
+pub(crate) async fn with_database<T, F>(db: &RedbDatabase, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&Database) -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let db = db.clone();
+    tokio::task::spawn_blocking(move || f(&db))
+        .await
+        .map_err(|e| Error::OtherError(Box::new(e)))?
+}
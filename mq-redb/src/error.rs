@@ -0,0 +1,5 @@
+use mq::Error;
+
+pub(crate) fn convert_redb_error<E: Into<redb::Error>>(err: E) -> Error {
+    Error::OtherError(Box::new(err.into()))
+}
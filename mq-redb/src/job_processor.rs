@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use mq::{Error, Job, JobProcessor};
+use serde_json::Value;
+
+use crate::{
+    database::{with_database, RedbDatabase},
+    error::convert_redb_error,
+    store::{now_millis, Tables},
+};
+
+pub struct RedbJobProcessor {
+    db: RedbDatabase,
+    table: String,
+}
+
+impl RedbJobProcessor {
+    pub fn new<T: Into<String>>(db: RedbDatabase, table: T) -> Self {
+        Self {
+            db,
+            table: table.into(),
+        }
+    }
+
+    async fn delete(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error> {
+        let tables = Tables::new(&self.table);
+        let (queue, kind, id) = (queue.to_owned(), kind.to_owned(), id.to_owned());
+
+        with_database(&self.db, move |db| {
+            let tx = db.begin_write().map_err(convert_redb_error)?;
+            tables.delete(&tx, &queue, &kind, &id)?;
+            tx.commit().map_err(convert_redb_error)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl JobProcessor for RedbJobProcessor {
+    async fn poll_next_job(&self, queues: &[&str]) -> Result<Option<Job>, Error> {
+        let tables = Tables::new(&self.table);
+        let queues: Vec<String> = queues.iter().map(|q| q.to_string()).collect();
+
+        with_database(&self.db, move |db| {
+            let tx = db.begin_write().map_err(convert_redb_error)?;
+            let job = tables.claim(&tx, &queues, now_millis())?;
+            tx.commit().map_err(convert_redb_error)?;
+            Ok(job)
+        })
+        .await
+    }
+
+    async fn complete_job_with_success(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<(), Error> {
+        self.delete(queue, kind, id).await
+    }
+
+    async fn complete_job_with_cancelled(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        _message: Option<String>,
+    ) -> Result<(), Error> {
+        self.delete(queue, kind, id).await
+    }
+
+    async fn fail_job(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        reason: Value,
+    ) -> Result<(), Error> {
+        let tables = Tables::new(&self.table);
+        let (queue, kind, id) = (queue.to_owned(), kind.to_owned(), id.to_owned());
+
+        with_database(&self.db, move |db| {
+            let tx = db.begin_write().map_err(convert_redb_error)?;
+            tables.fail(&tx, &queue, &kind, &id, reason, now_millis())?;
+            tx.commit().map_err(convert_redb_error)
+        })
+        .await
+    }
+}
@@ -0,0 +1,9 @@
+mod database;
+mod error;
+mod job_processor;
+mod producer;
+mod store;
+
+pub use database::*;
+pub use job_processor::*;
+pub use producer::*;
@@ -0,0 +1,114 @@
+use async_trait::async_trait;
+use mq::{Error, Job, Producer, PublishOutcome, QueueDepth};
+use redb::ReadableDatabase;
+
+use crate::{
+    database::{with_database, RedbDatabase},
+    error::convert_redb_error,
+    store::{now_millis, Tables},
+};
+
+pub struct RedbProducer {
+    db: RedbDatabase,
+    table: String,
+}
+
+impl RedbProducer {
+    pub fn new<T: Into<String>>(db: RedbDatabase, table: T) -> Self {
+        Self {
+            db,
+            table: table.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Producer for RedbProducer {
+    async fn publish(&self, job: Job) -> Result<PublishOutcome, Error> {
+        let outcome = self
+            .publish_many(vec![job])
+            .await?
+            .pop()
+            .expect("outcome of the published job");
+
+        Ok(outcome)
+    }
+
+    async fn exists(&self, queue: &str, kind: &str, id: &str) -> Result<bool, Error> {
+        let tables = Tables::new(&self.table);
+        let (queue, kind, id) = (queue.to_owned(), kind.to_owned(), id.to_owned());
+
+        with_database(&self.db, move |db| {
+            let tx = db.begin_read().map_err(convert_redb_error)?;
+            tables.exists(&tx, &queue, &kind, &id)
+        })
+        .await
+    }
+
+    async fn cancel_by_id(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error> {
+        let tables = Tables::new(&self.table);
+        let (queue, kind, id) = (queue.to_owned(), kind.to_owned(), id.to_owned());
+
+        with_database(&self.db, move |db| {
+            let tx = db.begin_write().map_err(convert_redb_error)?;
+            tables.delete(&tx, &queue, &kind, &id)?;
+            tx.commit().map_err(convert_redb_error)
+        })
+        .await
+    }
+
+    async fn cancel_by_unique_key(&self, queue: &str, kind: &str, key: &str) -> Result<(), Error> {
+        let tables = Tables::new(&self.table);
+        let (queue, kind, key) = (queue.to_owned(), kind.to_owned(), key.to_owned());
+
+        with_database(&self.db, move |db| {
+            let tx = db.begin_write().map_err(convert_redb_error)?;
+            tables.delete_by_unique_key(&tx, &queue, &kind, &key)?;
+            tx.commit().map_err(convert_redb_error)
+        })
+        .await
+    }
+
+    async fn publish_many(&self, jobs: Vec<Job>) -> Result<Vec<PublishOutcome>, Error> {
+        if jobs.iter().any(|job| !job.depends_on().is_empty()) {
+            return Err(Error::NotSupported("depends_on".into()));
+        }
+        // compensations of sagas are chained with depends_on
+        if jobs.iter().any(|job| !job.saga_steps().is_empty()) {
+            return Err(Error::NotSupported("saga_steps".into()));
+        }
+
+        let jobs: Vec<Job> = jobs.into_iter().map(Job::with_trace_context).collect();
+        let tables = Tables::new(&self.table);
+
+        with_database(&self.db, move |db| {
+            let tx = db.begin_write().map_err(convert_redb_error)?;
+            let now = now_millis();
+
+            // Dropping the transaction on error aborts it, so nothing is published.
+            let outcomes = jobs
+                .iter()
+                .map(|job| {
+                    tables
+                        .insert(&tx, job, now)?
+                        .check(job.on_unique_key_conflict())
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            tx.commit().map_err(convert_redb_error)?;
+            Ok(outcomes)
+        })
+        .await
+    }
+
+    async fn queue_depth(&self, queue: &str) -> Result<QueueDepth, Error> {
+        let tables = Tables::new(&self.table);
+        let queue = queue.to_owned();
+
+        with_database(&self.db, move |db| {
+            let tx = db.begin_read().map_err(convert_redb_error)?;
+            tables.queue_depth(&tx, &queue, now_millis())
+        })
+        .await
+    }
+}
@@ -0,0 +1,578 @@
+use std::collections::BTreeMap;
+
+use mq::{Error, Job, PublishOutcome, QueueDepth, UniqueKeyConflict};
+use redb::{ReadTransaction, ReadableTable, TableDefinition, TableError, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+use crate::error::convert_redb_error;
+
+type JobsTable<'a> = TableDefinition<'a, &'static str, &'static [u8]>;
+/// (queue, 255 - priority, available_at, id)
+type ReadyIndex<'a> = TableDefinition<'a, (&'static str, u8, i64, &'static str), ()>;
+/// (queue, kind, unique_key) -> id
+type UniqueIndex<'a> =
+    TableDefinition<'a, (&'static str, &'static str, &'static str), &'static str>;
+/// (locked_until, id) -> queue
+type LeaseIndex<'a> = TableDefinition<'a, (i64, &'static str), &'static str>;
+/// (queue, id)
+type DeadIndex<'a> = TableDefinition<'a, (&'static str, &'static str), ()>;
+
+pub(crate) fn now_millis() -> i64 {
+    to_millis(OffsetDateTime::now_utc())
+}
+
+fn to_millis(date_time: OffsetDateTime) -> i64 {
+    (date_time.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn millis_to_iso8601(millis: i64) -> Value {
+    OffsetDateTime::from_unix_timestamp_nanos(millis as i128 * 1_000_000)
+        .ok()
+        .and_then(|t| t.format(&Iso8601::DEFAULT).ok())
+        .into()
+}
+
+/// Job as stored in the jobs table. Timestamps are milliseconds since the epoch.
+#[derive(Serialize, Deserialize)]
+struct StoredJob {
+    id: String,
+    queue: String,
+    kind: String,
+    payload: Value,
+    headers: BTreeMap<String, Value>,
+    created_at: i64,
+    updated_at: i64,
+    scheduled_at: i64,
+    /// Time the job became available to poll, i.e. its scheduled time or the time its last
+    /// attempt failed, so retries go after the jobs of the same priority already waiting.
+    available_at: i64,
+    /// Expiry of the lease of the running attempt.
+    locked_until: Option<i64>,
+    attempts: u16,
+    max_attempts: u16,
+    priority: u8,
+    unique_key: Option<String>,
+    lease_time: u64,
+    error_reason: Option<Value>,
+}
+
+impl StoredJob {
+    fn new(job: &Job, now: i64) -> Self {
+        let scheduled_at = job.scheduled_at().map(to_millis).unwrap_or(now);
+        Self {
+            id: job.id().to_owned(),
+            queue: job.queue().to_owned(),
+            kind: job.kind().to_owned(),
+            payload: job.payload().clone(),
+            headers: job.headers().clone(),
+            created_at: now,
+            updated_at: now,
+            scheduled_at,
+            available_at: scheduled_at,
+            locked_until: None,
+            attempts: job.attempts(),
+            max_attempts: job.max_attempts(),
+            priority: job.priority(),
+            unique_key: job.unique_key().clone(),
+            lease_time: job.lease_time().as_secs(),
+            error_reason: None,
+        }
+    }
+
+    fn has_attempts_left(&self) -> bool {
+        self.attempts < self.max_attempts
+    }
+
+    /// Whether a worker runs the job and its lease has not expired.
+    fn is_leased(&self, now: i64) -> bool {
+        self.locked_until
+            .is_some_and(|locked_until| locked_until > now)
+    }
+
+    /// Lower ranks are polled first.
+    fn rank(&self) -> u8 {
+        u8::MAX - self.priority
+    }
+
+    fn to_job(&self) -> Result<Job, Error> {
+        Ok(serde_json::from_value(json!({
+            "id": self.id,
+            "queue": self.queue,
+            "kind": self.kind,
+            "payload": self.payload,
+            "headers": self.headers,
+            "created_at": millis_to_iso8601(self.created_at),
+            "updated_at": millis_to_iso8601(self.updated_at),
+            "scheduled_at": millis_to_iso8601(self.scheduled_at),
+            "attempts": self.attempts,
+            "max_attempts": self.max_attempts,
+            "priority": self.priority,
+            "unique_key": self.unique_key,
+            "lease_time": self.lease_time,
+            "error_reason": self.error_reason,
+            "workflow_id": null,
+            "batch_id": null,
+        }))?)
+    }
+}
+
+/// Jobs table and its secondary indexes.
+///
+/// Every job is referenced by exactly one of the ready, lease and dead indexes, and by the unique
+/// index while it has attempts left.
+pub(crate) struct Tables {
+    jobs: String,
+    ready: String,
+    unique: String,
+    leases: String,
+    dead: String,
+}
+
+impl Tables {
+    pub(crate) fn new(table: &str) -> Self {
+        Self {
+            jobs: table.to_owned(),
+            ready: format!("{table}_ready"),
+            unique: format!("{table}_unique"),
+            leases: format!("{table}_leases"),
+            dead: format!("{table}_dead"),
+        }
+    }
+
+    fn jobs(&self) -> JobsTable<'_> {
+        TableDefinition::new(&self.jobs)
+    }
+
+    fn ready(&self) -> ReadyIndex<'_> {
+        TableDefinition::new(&self.ready)
+    }
+
+    fn unique(&self) -> UniqueIndex<'_> {
+        TableDefinition::new(&self.unique)
+    }
+
+    fn leases(&self) -> LeaseIndex<'_> {
+        TableDefinition::new(&self.leases)
+    }
+
+    fn dead(&self) -> DeadIndex<'_> {
+        TableDefinition::new(&self.dead)
+    }
+
+    fn load(&self, tx: &WriteTransaction, id: &str) -> Result<Option<StoredJob>, Error> {
+        let jobs = tx.open_table(self.jobs()).map_err(convert_redb_error)?;
+        let job = jobs.get(id).map_err(convert_redb_error)?;
+        Ok(job
+            .map(|job| serde_json::from_slice(job.value()))
+            .transpose()?)
+    }
+
+    fn save(&self, tx: &WriteTransaction, job: &StoredJob) -> Result<(), Error> {
+        let mut jobs = tx.open_table(self.jobs()).map_err(convert_redb_error)?;
+        jobs.insert(job.id.as_str(), serde_json::to_vec(job)?.as_slice())
+            .map_err(convert_redb_error)?;
+        Ok(())
+    }
+
+    /// Adds the job to the index matching its state.
+    fn index(&self, tx: &WriteTransaction, job: &StoredJob) -> Result<(), Error> {
+        if let Some(locked_until) = job.locked_until {
+            let mut leases = tx.open_table(self.leases()).map_err(convert_redb_error)?;
+            leases
+                .insert((locked_until, job.id.as_str()), job.queue.as_str())
+                .map_err(convert_redb_error)?;
+        } else if job.has_attempts_left() {
+            let mut ready = tx.open_table(self.ready()).map_err(convert_redb_error)?;
+            ready
+                .insert(
+                    (
+                        job.queue.as_str(),
+                        job.rank(),
+                        job.available_at,
+                        job.id.as_str(),
+                    ),
+                    (),
+                )
+                .map_err(convert_redb_error)?;
+        } else {
+            let mut dead = tx.open_table(self.dead()).map_err(convert_redb_error)?;
+            dead.insert((job.queue.as_str(), job.id.as_str()), ())
+                .map_err(convert_redb_error)?;
+            self.release_unique_key(tx, job)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the job from the ready, lease and dead indexes.
+    fn unindex(&self, tx: &WriteTransaction, job: &StoredJob) -> Result<(), Error> {
+        let mut ready = tx.open_table(self.ready()).map_err(convert_redb_error)?;
+        ready
+            .remove((
+                job.queue.as_str(),
+                job.rank(),
+                job.available_at,
+                job.id.as_str(),
+            ))
+            .map_err(convert_redb_error)?;
+
+        if let Some(locked_until) = job.locked_until {
+            let mut leases = tx.open_table(self.leases()).map_err(convert_redb_error)?;
+            leases
+                .remove((locked_until, job.id.as_str()))
+                .map_err(convert_redb_error)?;
+        }
+
+        let mut dead = tx.open_table(self.dead()).map_err(convert_redb_error)?;
+        dead.remove((job.queue.as_str(), job.id.as_str()))
+            .map_err(convert_redb_error)?;
+        Ok(())
+    }
+
+    fn release_unique_key(&self, tx: &WriteTransaction, job: &StoredJob) -> Result<(), Error> {
+        let Some(unique_key) = &job.unique_key else {
+            return Ok(());
+        };
+
+        let mut unique = tx.open_table(self.unique()).map_err(convert_redb_error)?;
+        let key = (job.queue.as_str(), job.kind.as_str(), unique_key.as_str());
+        let owned = unique
+            .get(key)
+            .map_err(convert_redb_error)?
+            .is_some_and(|id| id.value() == job.id);
+        if owned {
+            unique.remove(key).map_err(convert_redb_error)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts the job, or applies its unique key conflict strategy to the job with attempts left
+    /// holding its unique key. A leased job is not replaced or rescheduled, the job is skipped.
+    pub(crate) fn insert(
+        &self,
+        tx: &WriteTransaction,
+        job: &Job,
+        now: i64,
+    ) -> Result<PublishOutcome, Error> {
+        let on_unique_key_conflict = job.on_unique_key_conflict();
+        let job = StoredJob::new(job, now);
+
+        if let Some(unique_key) = &job.unique_key {
+            let key = (job.queue.as_str(), job.kind.as_str(), unique_key.as_str());
+            let existing = {
+                let unique = tx.open_table(self.unique()).map_err(convert_redb_error)?;
+                let existing = unique.get(key).map_err(convert_redb_error)?;
+                existing.map(|id| id.value().to_owned())
+            };
+            let existing = match existing {
+                Some(existing) => self.load(tx, &existing)?,
+                None => None,
+            };
+            if let Some(mut existing) = existing.filter(StoredJob::has_attempts_left) {
+                let id = existing.id.clone();
+                match on_unique_key_conflict {
+                    UniqueKeyConflict::Skip | UniqueKeyConflict::Error => {
+                        return Ok(PublishOutcome::Duplicate(id));
+                    }
+                    _ if existing.is_leased(now) => return Ok(PublishOutcome::Duplicate(id)),
+                    UniqueKeyConflict::Replace | UniqueKeyConflict::Reschedule => {
+                        self.unindex(tx, &existing)?;
+                        if on_unique_key_conflict == UniqueKeyConflict::Replace {
+                            existing.payload = job.payload;
+                        }
+                        existing.scheduled_at = job.scheduled_at;
+                        existing.available_at = job.available_at;
+                        self.index(tx, &existing)?;
+                        self.save(tx, &existing)?;
+                        return Ok(PublishOutcome::Replaced(id));
+                    }
+                }
+            }
+        }
+
+        if self.load(tx, &job.id)?.is_some() {
+            return Err(Error::UnknownError(format!(
+                "job {} already exists",
+                job.id
+            )));
+        }
+
+        if let Some(unique_key) = &job.unique_key {
+            let mut unique = tx.open_table(self.unique()).map_err(convert_redb_error)?;
+            unique
+                .insert(
+                    (job.queue.as_str(), job.kind.as_str(), unique_key.as_str()),
+                    job.id.as_str(),
+                )
+                .map_err(convert_redb_error)?;
+        }
+
+        self.save(tx, &job)?;
+        self.index(tx, &job)?;
+        Ok(PublishOutcome::Published)
+    }
+
+    /// Makes the jobs whose lease expired ready again, or dead when they exhausted their attempts.
+    fn release_expired_leases(&self, tx: &WriteTransaction, now: i64) -> Result<(), Error> {
+        let expired = {
+            let leases = tx.open_table(self.leases()).map_err(convert_redb_error)?;
+            let range = leases.range(..(now, "")).map_err(convert_redb_error)?;
+            range
+                .map(|entry| {
+                    let (key, _) = entry.map_err(convert_redb_error)?;
+                    Ok(key.value().1.to_owned())
+                })
+                .collect::<Result<Vec<_>, Error>>()?
+        };
+
+        for id in expired {
+            if let Some(mut job) = self.load(tx, &id)? {
+                self.unindex(tx, &job)?;
+                job.locked_until = None;
+                self.index(tx, &job)?;
+                self.save(tx, &job)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// First job of the queue that is due, skipping the priorities whose jobs are all scheduled
+    /// in the future. Returns its rank, its availability and its id.
+    fn next_ready(
+        &self,
+        tx: &WriteTransaction,
+        queue: &str,
+        now: i64,
+    ) -> Result<Option<(u8, i64, String)>, Error> {
+        let ready = tx.open_table(self.ready()).map_err(convert_redb_error)?;
+        let mut rank = 0;
+
+        loop {
+            let mut range = ready
+                .range((queue, rank, i64::MIN, "")..)
+                .map_err(convert_redb_error)?;
+            let Some(entry) = range.next() else {
+                return Ok(None);
+            };
+            let (key, _) = entry.map_err(convert_redb_error)?;
+            let (job_queue, job_rank, available_at, id) = key.value();
+
+            if job_queue != queue {
+                return Ok(None);
+            }
+            if available_at <= now {
+                return Ok(Some((job_rank, available_at, id.to_owned())));
+            }
+            if job_rank == u8::MAX {
+                return Ok(None);
+            }
+            rank = job_rank + 1;
+        }
+    }
+
+    /// Leases the next job of the queues.
+    pub(crate) fn claim(
+        &self,
+        tx: &WriteTransaction,
+        queues: &[String],
+        now: i64,
+    ) -> Result<Option<Job>, Error> {
+        self.release_expired_leases(tx, now)?;
+
+        let mut next: Option<(u8, i64, String)> = None;
+        for queue in queues {
+            if let Some(candidate) = self.next_ready(tx, queue, now)? {
+                if next
+                    .as_ref()
+                    .is_none_or(|next| (candidate.0, candidate.1) < (next.0, next.1))
+                {
+                    next = Some(candidate);
+                }
+            }
+        }
+
+        let Some((_, _, id)) = next else {
+            return Ok(None);
+        };
+        let Some(mut job) = self.load(tx, &id)? else {
+            return Ok(None);
+        };
+
+        self.unindex(tx, &job)?;
+        job.attempts += 1;
+        job.updated_at = now;
+        job.locked_until = Some(now + job.lease_time as i64 * 1000);
+        self.index(tx, &job)?;
+        self.save(tx, &job)?;
+
+        job.to_job().map(Some)
+    }
+
+    /// Deletes the job, returning false when it does not exist.
+    pub(crate) fn delete(
+        &self,
+        tx: &WriteTransaction,
+        queue: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<bool, Error> {
+        let Some(job) = self.load(tx, id)? else {
+            return Ok(false);
+        };
+        if job.queue != queue || job.kind != kind {
+            return Ok(false);
+        }
+
+        self.unindex(tx, &job)?;
+        self.release_unique_key(tx, &job)?;
+        let mut jobs = tx.open_table(self.jobs()).map_err(convert_redb_error)?;
+        jobs.remove(id).map_err(convert_redb_error)?;
+        Ok(true)
+    }
+
+    /// Deletes the job holding the unique key, returning false when there is none.
+    pub(crate) fn delete_by_unique_key(
+        &self,
+        tx: &WriteTransaction,
+        queue: &str,
+        kind: &str,
+        unique_key: &str,
+    ) -> Result<bool, Error> {
+        let id = {
+            let unique = tx.open_table(self.unique()).map_err(convert_redb_error)?;
+            let id = unique
+                .get((queue, kind, unique_key))
+                .map_err(convert_redb_error)?;
+            id.map(|id| id.value().to_owned())
+        };
+
+        match id {
+            Some(id) => self.delete(tx, queue, kind, &id),
+            None => Ok(false),
+        }
+    }
+
+    /// Releases the lease of the job, which is ready again unless it exhausted its attempts.
+    pub(crate) fn fail(
+        &self,
+        tx: &WriteTransaction,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        reason: Value,
+        now: i64,
+    ) -> Result<(), Error> {
+        let Some(mut job) = self.load(tx, id)? else {
+            return Ok(());
+        };
+        if job.queue != queue || job.kind != kind {
+            return Ok(());
+        }
+
+        self.unindex(tx, &job)?;
+        job.locked_until = None;
+        job.updated_at = now;
+        job.available_at = now;
+        job.error_reason = Some(reason);
+        self.index(tx, &job)?;
+        self.save(tx, &job)
+    }
+
+    pub(crate) fn exists(
+        &self,
+        tx: &ReadTransaction,
+        queue: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<bool, Error> {
+        let jobs = match tx.open_table(self.jobs()) {
+            Ok(jobs) => jobs,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(false),
+            Err(e) => return Err(convert_redb_error(e)),
+        };
+        let Some(job) = jobs.get(id).map_err(convert_redb_error)? else {
+            return Ok(false);
+        };
+
+        let job: StoredJob = serde_json::from_slice(job.value())?;
+        Ok(job.queue == queue && job.kind == kind)
+    }
+
+    pub(crate) fn queue_depth(
+        &self,
+        tx: &ReadTransaction,
+        queue: &str,
+        now: i64,
+    ) -> Result<QueueDepth, Error> {
+        let mut depth = QueueDepth::default();
+
+        match tx.open_table(self.ready()) {
+            Ok(ready) => {
+                for entry in ready
+                    .range((queue, 0, i64::MIN, "")..)
+                    .map_err(convert_redb_error)?
+                {
+                    let (key, _) = entry.map_err(convert_redb_error)?;
+                    let (job_queue, _, available_at, _) = key.value();
+                    if job_queue != queue {
+                        break;
+                    }
+                    if available_at <= now {
+                        depth.ready += 1;
+                    } else {
+                        depth.scheduled += 1;
+                    }
+                }
+            }
+            Err(TableError::TableDoesNotExist(_)) => {}
+            Err(e) => return Err(convert_redb_error(e)),
+        }
+
+        match tx.open_table(self.leases()) {
+            Ok(leases) => {
+                for entry in leases.iter().map_err(convert_redb_error)? {
+                    let (key, value) = entry.map_err(convert_redb_error)?;
+                    if value.value() != queue {
+                        continue;
+                    }
+                    let (locked_until, id) = key.value();
+                    if locked_until >= now {
+                        depth.running += 1;
+                        continue;
+                    }
+
+                    // released by the next poll
+                    let jobs = tx.open_table(self.jobs()).map_err(convert_redb_error)?;
+                    let job = jobs.get(id).map_err(convert_redb_error)?;
+                    let job: Option<StoredJob> = job
+                        .map(|job| serde_json::from_slice(job.value()))
+                        .transpose()?;
+                    if job.is_some_and(|job| job.has_attempts_left()) {
+                        depth.ready += 1;
+                    } else {
+                        depth.dead += 1;
+                    }
+                }
+            }
+            Err(TableError::TableDoesNotExist(_)) => {}
+            Err(e) => return Err(convert_redb_error(e)),
+        }
+
+        match tx.open_table(self.dead()) {
+            Ok(dead) => {
+                for entry in dead.range((queue, "")..).map_err(convert_redb_error)? {
+                    let (key, _) = entry.map_err(convert_redb_error)?;
+                    if key.value().0 != queue {
+                        break;
+                    }
+                    depth.dead += 1;
+                }
+            }
+            Err(TableError::TableDoesNotExist(_)) => {}
+            Err(e) => return Err(convert_redb_error(e)),
+        }
+
+        Ok(depth)
+    }
+}
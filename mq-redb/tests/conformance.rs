@@ -0,0 +1,16 @@
+use mq_redb::{RedbJobProcessor, RedbProducer};
+use mq_testsuite::Backend;
+
+const TABLE: &str = "jobs";
+
+async fn backend() -> Backend {
+    // the file is deleted at the end of this function, the open database keeps using it
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let db = mq_redb::open(file.path()).unwrap();
+    Backend::new(
+        RedbProducer::new(db.clone(), TABLE),
+        RedbJobProcessor::new(db, TABLE),
+    )
+}
+
+mq_testsuite::backend_tests!(backend);
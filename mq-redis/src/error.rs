@@ -0,0 +1,5 @@
+use mq::Error;
+
+pub(crate) fn convert_redis_error(err: redis::RedisError) -> Error {
+    Error::OtherError(Box::new(err))
+}
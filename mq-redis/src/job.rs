@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use mq::{Error, Job};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+pub(crate) fn to_millis(date_time: OffsetDateTime) -> i64 {
+    (date_time.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn millis_to_iso8601(millis: Option<&String>) -> Value {
+    millis
+        .and_then(|m| m.parse::<i128>().ok())
+        .and_then(|m| OffsetDateTime::from_unix_timestamp_nanos(m * 1_000_000).ok())
+        .and_then(|t| t.format(&Iso8601::DEFAULT).ok())
+        .into()
+}
+
+fn parse_json(text: Option<&String>) -> Value {
+    text.and_then(|t| serde_json::from_str(t).ok())
+        .unwrap_or(Value::Null)
+}
+
+fn parse_number(text: Option<&String>) -> Value {
+    text.and_then(|t| t.parse::<u64>().ok()).into()
+}
+
+/// Convert the fields of a job hash to a job.
+pub(crate) fn hash_to_job(hash: HashMap<String, String>) -> Result<Job, Error> {
+    Ok(serde_json::from_value(json!({
+        "id": hash.get("id"),
+        "queue": hash.get("queue"),
+        "kind": hash.get("kind"),
+        "payload": parse_json(hash.get("payload")),
+        "headers": parse_json(hash.get("headers")),
+        "created_at": millis_to_iso8601(hash.get("created_at")),
+        "updated_at": millis_to_iso8601(hash.get("updated_at")),
+        "scheduled_at": millis_to_iso8601(hash.get("scheduled_at")),
+        "attempts": parse_number(hash.get("attempts")),
+        "max_attempts": parse_number(hash.get("max_attempts")),
+        "priority": parse_number(hash.get("priority")),
+        "unique_key": hash.get("unique_key").filter(|k| !k.is_empty()),
+        "lease_time": parse_number(hash.get("lease_time")),
+        "error_reason": parse_json(hash.get("error_reason")),
+        "workflow_id": null,
+        "batch_id": null,
+    }))?)
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt};
+use mq::{Error, Job, JobProcessor};
+use redis::aio::ConnectionManager;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{error::convert_redis_error, job::hash_to_job, scripts};
+
+pub struct RedisJobProcessor {
+    conn: ConnectionManager,
+    prefix: String,
+    client: Option<redis::Client>,
+}
+
+impl RedisJobProcessor {
+    pub fn new<T: Into<String>>(conn: ConnectionManager, prefix: T) -> Self {
+        Self {
+            conn,
+            prefix: prefix.into(),
+            client: None,
+        }
+    }
+
+    /// Client used to subscribe to the notifications sent when jobs are published, so the
+    /// worker polls right away instead of waiting for the next poll interval.
+    pub fn with_client(mut self, client: redis::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    async fn delete(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error> {
+        let _: i64 = scripts::DELETE
+            .prepare_invoke()
+            .arg(&self.prefix)
+            .arg(id)
+            .arg(queue)
+            .arg(kind)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(convert_redis_error)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl JobProcessor for RedisJobProcessor {
+    async fn poll_next_job(&self, queues: &[&str]) -> Result<Option<Job>, Error> {
+        if queues.is_empty() {
+            return Ok(None);
+        }
+
+        let job: Option<HashMap<String, String>> = scripts::CLAIM
+            .prepare_invoke()
+            .arg(&self.prefix)
+            .arg(queues)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(convert_redis_error)?;
+
+        job.map(hash_to_job).transpose()
+    }
+
+    async fn job_notifications(
+        &self,
+        queues: &[&str],
+    ) -> Result<Option<BoxStream<'static, ()>>, Error> {
+        let Some(client) = &self.client else {
+            return Ok(None);
+        };
+
+        let mut pubsub = client
+            .get_async_pubsub()
+            .await
+            .map_err(convert_redis_error)?;
+        pubsub
+            .subscribe(format!("{}:notify", self.prefix))
+            .await
+            .map_err(convert_redis_error)?;
+
+        let queues: Vec<String> = queues.iter().map(|q| q.to_string()).collect();
+        let notifications = pubsub.into_on_message().filter_map(move |message| {
+            let notify = match message.get_payload::<String>() {
+                Ok(queue) => queues.contains(&queue),
+                Err(e) => {
+                    warn!("Failed to receive job notification: {:?}", e);
+                    false
+                }
+            };
+            async move { notify.then_some(()) }
+        });
+
+        Ok(Some(notifications.boxed()))
+    }
+
+    async fn complete_job_with_success(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<(), Error> {
+        self.delete(queue, kind, id).await
+    }
+
+    async fn complete_job_with_cancelled(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        _message: Option<String>,
+    ) -> Result<(), Error> {
+        self.delete(queue, kind, id).await
+    }
+
+    async fn fail_job(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        reason: Value,
+    ) -> Result<(), Error> {
+        let _: i64 = scripts::FAIL
+            .prepare_invoke()
+            .arg(&self.prefix)
+            .arg(id)
+            .arg(queue)
+            .arg(kind)
+            .arg(serde_json::to_string(&reason)?)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(convert_redis_error)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,8 @@
+mod error;
+mod job;
+mod job_processor;
+mod producer;
+mod scripts;
+
+pub use job_processor::*;
+pub use producer::*;
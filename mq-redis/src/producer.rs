@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use mq::{Error, Job, Producer, PublishOutcome, QueueDepth};
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::{error::convert_redis_error, job::to_millis, scripts};
+
+pub struct RedisProducer {
+    conn: ConnectionManager,
+    prefix: String,
+}
+
+impl RedisProducer {
+    pub fn new<T: Into<String>>(conn: ConnectionManager, prefix: T) -> Self {
+        Self {
+            conn,
+            prefix: prefix.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Producer for RedisProducer {
+    async fn publish(&self, job: Job) -> Result<PublishOutcome, Error> {
+        if !job.depends_on().is_empty() {
+            return Err(Error::NotSupported("depends_on".into()));
+        }
+        // compensations of sagas are chained with depends_on
+        if !job.saga_steps().is_empty() {
+            return Err(Error::NotSupported("saga_steps".into()));
+        }
+
+        let job = job.with_trace_context();
+
+        let (outcome, id): (String, String) = scripts::PUBLISH
+            .prepare_invoke()
+            .arg(&self.prefix)
+            .arg(job.id())
+            .arg(job.queue())
+            .arg(job.kind())
+            .arg(serde_json::to_string(job.payload())?)
+            .arg(serde_json::to_string(job.headers())?)
+            .arg(
+                job.scheduled_at()
+                    .map(|t| to_millis(t).to_string())
+                    .unwrap_or_default(),
+            )
+            .arg(job.attempts())
+            .arg(job.max_attempts())
+            .arg(job.priority())
+            .arg(job.unique_key().as_deref().unwrap_or_default())
+            .arg(job.lease_time().as_secs())
+            .arg(job.on_unique_key_conflict().as_str())
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(convert_redis_error)?;
+
+        let outcome = match outcome.as_str() {
+            "duplicate" => PublishOutcome::Duplicate(id),
+            "replaced" => PublishOutcome::Replaced(id),
+            _ => PublishOutcome::Published,
+        };
+        outcome.check(job.on_unique_key_conflict())
+    }
+
+    async fn exists(&self, queue: &str, kind: &str, id: &str) -> Result<bool, Error> {
+        let (job_queue, job_kind): (Option<String>, Option<String>) = self
+            .conn
+            .clone()
+            .hget(format!("{}:job:{}", self.prefix, id), &["queue", "kind"])
+            .await
+            .map_err(convert_redis_error)?;
+
+        Ok(job_queue.as_deref() == Some(queue) && job_kind.as_deref() == Some(kind))
+    }
+
+    async fn cancel_by_id(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error> {
+        let _: i64 = scripts::DELETE
+            .prepare_invoke()
+            .arg(&self.prefix)
+            .arg(id)
+            .arg(queue)
+            .arg(kind)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(convert_redis_error)?;
+
+        Ok(())
+    }
+
+    async fn cancel_by_unique_key(&self, queue: &str, kind: &str, key: &str) -> Result<(), Error> {
+        let _: i64 = scripts::DELETE_BY_UNIQUE_KEY
+            .prepare_invoke()
+            .arg(&self.prefix)
+            .arg(queue)
+            .arg(kind)
+            .arg(key)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(convert_redis_error)?;
+
+        Ok(())
+    }
+
+    async fn queue_depth(&self, queue: &str) -> Result<QueueDepth, Error> {
+        let (ready, scheduled, running, dead): (u64, u64, u64, u64) = scripts::QUEUE_DEPTH
+            .prepare_invoke()
+            .arg(&self.prefix)
+            .arg(queue)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .map_err(convert_redis_error)?;
+
+        Ok(QueueDepth {
+            ready,
+            scheduled,
+            running,
+            dead,
+        })
+    }
+}
@@ -0,0 +1,315 @@
+//! Lua scripts keeping the job hashes and the sorted sets of their queue consistent.
+//!
+//! Every job is stored in the `{prefix}:job:{id}` hash and referenced by exactly one of the
+//! sorted sets of its queue:
+//!
+//! * `{prefix}:queue:{queue}:scheduled` scored by `scheduled_at` until the job is due.
+//! * `{prefix}:queue:{queue}:ready` scored by priority, then by `updated_at`.
+//! * `{prefix}:queue:{queue}:leased` scored by the expiry of the lease of the running attempt.
+//! * `{prefix}:queue:{queue}:dead` scored by the time the job exhausted its attempts.
+//!
+//! The `{prefix}:unique` hash maps the `unique_key` of the active jobs to their id. Timestamps
+//! are milliseconds since the epoch taken from the clock of the server.
+
+use std::sync::LazyLock;
+
+use redis::Script;
+
+macro_rules! helpers {
+    () => {
+        r#"
+local function now_ms()
+    local time = redis.call('TIME')
+    return tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+end
+
+-- Lua numbers are formatted with 14 significant digits, which is not enough for the scores.
+local function fmt(number)
+    return string.format('%.0f', number)
+end
+
+-- Higher priority first, then the least recently updated.
+local function ready_score(priority, updated_at)
+    return (255 - tonumber(priority)) * 10000000000000 + tonumber(updated_at)
+end
+
+local function queue_key(prefix, queue)
+    return prefix .. ':queue:' .. queue
+end
+
+local function job_key(prefix, id)
+    return prefix .. ':job:' .. id
+end
+
+local function unique_field(queue, kind, unique_key)
+    return queue .. '\0' .. kind .. '\0' .. unique_key
+end
+
+local function release_unique(prefix, queue, kind, unique_key, id)
+    if unique_key and unique_key ~= '' then
+        local field = unique_field(queue, kind, unique_key)
+        if redis.call('HGET', prefix .. ':unique', field) == id then
+            redis.call('HDEL', prefix .. ':unique', field)
+        end
+    end
+end
+
+local function kill(prefix, queue, kind, unique_key, id, now)
+    redis.call('ZADD', queue_key(prefix, queue) .. ':dead', fmt(now), id)
+    release_unique(prefix, queue, kind, unique_key, id)
+end
+
+local function delete_job(prefix, id, queue, kind)
+    local job = redis.call('HMGET', job_key(prefix, id), 'queue', 'kind', 'unique_key')
+    if job[1] ~= queue or job[2] ~= kind then
+        return 0
+    end
+
+    redis.call('DEL', job_key(prefix, id))
+    for _, state in ipairs({ 'scheduled', 'ready', 'leased', 'dead' }) do
+        redis.call('ZREM', queue_key(prefix, queue) .. ':' .. state, id)
+    end
+    release_unique(prefix, queue, kind, job[3], id)
+    return 1
+end
+"#
+    };
+}
+
+/// ARGV: prefix, id, queue, kind, payload, headers, scheduled_at (or empty), attempts,
+/// max_attempts, priority, unique_key (or empty), lease_time, on_unique_key_conflict.
+///
+/// Returns `{'published', id}`, or `{'duplicate', id}` and `{'replaced', id}` with the id of the
+/// active job already holding the unique key when the job conflicts with it. A leased job is not
+/// replaced or rescheduled, the job is a duplicate.
+pub(crate) static PUBLISH: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(concat!(
+        helpers!(),
+        r#"
+local prefix, id, queue, kind = ARGV[1], ARGV[2], ARGV[3], ARGV[4]
+local priority, unique_key, on_unique_key_conflict = ARGV[10], ARGV[11], ARGV[13]
+
+local now = now_ms()
+local scheduled_at = now
+if ARGV[7] ~= '' then
+    scheduled_at = tonumber(ARGV[7])
+end
+
+if unique_key ~= '' then
+    local existing = redis.call('HGET', prefix .. ':unique', unique_field(queue, kind, unique_key))
+    if existing then
+        local existing_key = job_key(prefix, existing)
+        local job = redis.call('HMGET', existing_key, 'attempts', 'max_attempts', 'priority',
+            'updated_at')
+        if job[1] and tonumber(job[1]) < tonumber(job[2]) then
+            local key = queue_key(prefix, queue)
+            local locked_until = redis.call('ZSCORE', key .. ':leased', existing)
+            -- Leased jobs are not replaced or rescheduled while their worker runs them.
+            if (on_unique_key_conflict ~= 'replace' and on_unique_key_conflict ~= 'reschedule')
+                or (locked_until and tonumber(locked_until) > now) then
+                return { 'duplicate', existing }
+            end
+
+            if on_unique_key_conflict == 'replace' then
+                redis.call('HSET', existing_key, 'payload', ARGV[5])
+            end
+            redis.call('HSET', existing_key, 'scheduled_at', fmt(scheduled_at))
+
+            -- Jobs whose lease expired are released by the next poll, the new schedule only
+            -- applies to their next attempt.
+            local waiting = redis.call('ZREM', key .. ':scheduled', existing)
+                + redis.call('ZREM', key .. ':ready', existing)
+            if waiting > 0 then
+                if scheduled_at <= now then
+                    redis.call('ZADD', key .. ':ready', fmt(ready_score(job[3], job[4])), existing)
+                else
+                    redis.call('ZADD', key .. ':scheduled', fmt(scheduled_at), existing)
+                end
+                redis.call('PUBLISH', prefix .. ':notify', queue)
+            end
+            return { 'replaced', existing }
+        end
+    end
+end
+
+if redis.call('EXISTS', job_key(prefix, id)) == 1 then
+    return redis.error_reply('job ' .. id .. ' already exists')
+end
+
+redis.call('HSET', job_key(prefix, id),
+    'id', id,
+    'queue', queue,
+    'kind', kind,
+    'payload', ARGV[5],
+    'headers', ARGV[6],
+    'created_at', fmt(now),
+    'updated_at', fmt(now),
+    'scheduled_at', fmt(scheduled_at),
+    'locked_at', '',
+    'attempts', ARGV[8],
+    'max_attempts', ARGV[9],
+    'priority', priority,
+    'unique_key', unique_key,
+    'lease_time', ARGV[12],
+    'error_reason', '')
+
+if unique_key ~= '' then
+    redis.call('HSET', prefix .. ':unique', unique_field(queue, kind, unique_key), id)
+end
+
+if tonumber(ARGV[8]) >= tonumber(ARGV[9]) then
+    kill(prefix, queue, kind, unique_key, id, now)
+elseif scheduled_at <= now then
+    redis.call('ZADD', queue_key(prefix, queue) .. ':ready', fmt(ready_score(priority, now)), id)
+else
+    redis.call('ZADD', queue_key(prefix, queue) .. ':scheduled', fmt(scheduled_at), id)
+end
+
+redis.call('PUBLISH', prefix .. ':notify', queue)
+return { 'published', id }
+"#
+    ))
+});
+
+/// ARGV: prefix, queues...
+///
+/// Moves at most 100 due and 100 expired jobs of each queue to its ready set, so a backlog of them
+/// is moved over the next claims instead of blocking the server, then leases the next job and
+/// returns its hash, or nil when there is none.
+pub(crate) static CLAIM: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(concat!(
+        helpers!(),
+        r#"
+local prefix = ARGV[1]
+local now = now_ms()
+local sweep_limit = 100
+local next_id, next_score, next_queue = nil, nil, nil
+
+for i = 2, #ARGV do
+    local queue = ARGV[i]
+    local key = queue_key(prefix, queue)
+
+    for _, id in ipairs(redis.call('ZRANGEBYSCORE', key .. ':scheduled', '-inf', fmt(now),
+        'LIMIT', 0, sweep_limit)) do
+        local job = redis.call('HMGET', job_key(prefix, id), 'priority', 'updated_at')
+        redis.call('ZREM', key .. ':scheduled', id)
+        redis.call('ZADD', key .. ':ready', fmt(ready_score(job[1], job[2])), id)
+    end
+
+    for _, id in ipairs(redis.call('ZRANGEBYSCORE', key .. ':leased', '-inf', '(' .. fmt(now),
+        'LIMIT', 0, sweep_limit)) do
+        local job = redis.call('HMGET', job_key(prefix, id),
+            'attempts', 'max_attempts', 'priority', 'updated_at', 'kind', 'unique_key')
+        redis.call('ZREM', key .. ':leased', id)
+        if tonumber(job[1]) < tonumber(job[2]) then
+            redis.call('ZADD', key .. ':ready', fmt(ready_score(job[3], job[4])), id)
+        else
+            kill(prefix, queue, job[5], job[6], id, now)
+        end
+    end
+
+    local head = redis.call('ZRANGE', key .. ':ready', 0, 0, 'WITHSCORES')
+    if head[1] and (next_score == nil or tonumber(head[2]) < next_score) then
+        next_id, next_score, next_queue = head[1], tonumber(head[2]), queue
+    end
+end
+
+if not next_id then
+    return nil
+end
+
+local key = queue_key(prefix, next_queue)
+local job = job_key(prefix, next_id)
+local lease_time = tonumber(redis.call('HGET', job, 'lease_time'))
+
+redis.call('ZREM', key .. ':ready', next_id)
+redis.call('ZADD', key .. ':leased', fmt(now + lease_time * 1000), next_id)
+redis.call('HINCRBY', job, 'attempts', 1)
+redis.call('HSET', job, 'locked_at', fmt(now), 'updated_at', fmt(now))
+return redis.call('HGETALL', job)
+"#
+    ))
+});
+
+/// ARGV: prefix, id, queue, kind.
+///
+/// Deletes the job, returning 0 when it does not exist.
+pub(crate) static DELETE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(concat!(
+        helpers!(),
+        r#"
+return delete_job(ARGV[1], ARGV[2], ARGV[3], ARGV[4])
+"#
+    ))
+});
+
+/// ARGV: prefix, queue, kind, unique_key.
+///
+/// Deletes the job holding the unique key, returning 0 when there is none.
+pub(crate) static DELETE_BY_UNIQUE_KEY: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(concat!(
+        helpers!(),
+        r#"
+local prefix, queue, kind = ARGV[1], ARGV[2], ARGV[3]
+local id = redis.call('HGET', prefix .. ':unique', unique_field(queue, kind, ARGV[4]))
+if not id then
+    return 0
+end
+return delete_job(prefix, id, queue, kind)
+"#
+    ))
+});
+
+/// ARGV: prefix, id, queue, kind, error_reason.
+///
+/// Releases the lease of the job and makes it ready again, or dead when it exhausted its
+/// attempts.
+pub(crate) static FAIL: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(concat!(
+        helpers!(),
+        r#"
+local prefix, id, queue, kind = ARGV[1], ARGV[2], ARGV[3], ARGV[4]
+local job = redis.call('HMGET', job_key(prefix, id),
+    'queue', 'kind', 'attempts', 'max_attempts', 'priority', 'unique_key')
+if job[1] ~= queue or job[2] ~= kind then
+    return 0
+end
+
+local now = now_ms()
+redis.call('HSET', job_key(prefix, id),
+    'locked_at', '',
+    'updated_at', fmt(now),
+    'error_reason', ARGV[5])
+redis.call('ZREM', queue_key(prefix, queue) .. ':leased', id)
+
+if tonumber(job[3]) < tonumber(job[4]) then
+    redis.call('ZADD', queue_key(prefix, queue) .. ':ready', fmt(ready_score(job[5], now)), id)
+else
+    kill(prefix, queue, kind, job[6], id, now)
+end
+return 1
+"#
+    ))
+});
+
+/// ARGV: prefix, queue.
+///
+/// Returns the ready, scheduled, running and dead counts of the queue.
+pub(crate) static QUEUE_DEPTH: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(concat!(
+        helpers!(),
+        r#"
+local key = queue_key(ARGV[1], ARGV[2])
+local now = now_ms()
+
+local ready = redis.call('ZCARD', key .. ':ready')
+    + redis.call('ZCOUNT', key .. ':scheduled', '-inf', fmt(now))
+    + redis.call('ZCOUNT', key .. ':leased', '-inf', '(' .. fmt(now))
+local scheduled = redis.call('ZCOUNT', key .. ':scheduled', '(' .. fmt(now), '+inf')
+local running = redis.call('ZCOUNT', key .. ':leased', fmt(now), '+inf')
+local dead = redis.call('ZCARD', key .. ':dead')
+
+return { ready, scheduled, running, dead }
+"#
+    ))
+});
@@ -0,0 +1,54 @@
+//! Runs against the Redis server at `REDIS_URL`:
+//!
+//! ```sh
+//! REDIS_URL=redis://127.0.0.1/ cargo test -p mq-redis -- --ignored
+//! ```
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use mq_redis::{RedisJobProcessor, RedisProducer};
+use mq_testsuite::Backend;
+
+async fn backend() -> Backend {
+    let url = std::env::var("REDIS_URL").expect("REDIS_URL to be set");
+    let client = redis::Client::open(url).unwrap();
+    let conn = client.get_connection_manager().await.unwrap();
+
+    // every test uses its own keys
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let prefix = format!("mq-test-{nanos}");
+
+    Backend::new(
+        RedisProducer::new(conn.clone(), &prefix),
+        RedisJobProcessor::new(conn, &prefix).with_client(client),
+    )
+}
+
+// publish_many is not supported
+mq_testsuite::backend_tests!(
+    #[ignore = "needs a Redis server at REDIS_URL"]
+    backend;
+    polls_higher_priority_first,
+    polls_same_priority_in_publish_order,
+    skips_jobs_scheduled_in_the_future,
+    polls_only_the_requested_queues,
+    does_not_poll_leased_jobs,
+    redelivers_jobs_after_lease_expiry,
+    counts_attempts_until_exhausted,
+    rejects_unique_key_while_active,
+    releases_unique_key_after_completion,
+    reports_publish_outcomes,
+    replaces_job_on_unique_key_conflict,
+    reschedules_job_on_unique_key_conflict,
+    keeps_leased_job_on_unique_key_conflict,
+    fails_publish_on_unique_key_conflict,
+    publish_and_wait_publishes_only_if_supported,
+    complete_with_success_removes_job,
+    complete_with_cancelled_removes_job,
+    cancel_by_id_removes_job,
+    cancel_by_unique_key_removes_job,
+    exists_checks_queue_and_kind,
+);
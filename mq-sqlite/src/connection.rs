@@ -0,0 +1,92 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use mq::Error;
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+use crate::error::convert_sqlite_error;
+
+/// Connection shared by the producer and the job processor.
+pub type SqliteConnection = Arc<Mutex<Connection>>;
+
+/// Open the database in WAL mode so readers do not block the writer.
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Connection, Error> {
+    let conn = Connection::open(path).map_err(convert_sqlite_error)?;
+    conn.pragma_update(None, "journal_mode", "WAL")
+        .map_err(convert_sqlite_error)?;
+    conn.busy_timeout(Duration::from_secs(5))
+        .map_err(convert_sqlite_error)?;
+    Ok(conn)
+}
+
+/// Run the closure on a blocking thread with the locked connection.
+pub(crate) async fn with_connection<T, F>(db: &SqliteConnection, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let db = db.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = db.lock().unwrap();
+        f(&mut conn)
+    })
+    .await
+    .map_err(|e| Error::OtherError(Box::new(e)))?
+    .map_err(convert_sqlite_error)
+}
+
+/// Quote the table name so it can be interpolated in statements.
+pub(crate) fn quote(table: &str) -> String {
+    format!("\"{}\"", table.replace('"', "\"\""))
+}
+
+pub(crate) fn now_millis() -> i64 {
+    to_millis(OffsetDateTime::now_utc())
+}
+
+pub(crate) fn to_millis(date_time: OffsetDateTime) -> i64 {
+    (date_time.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn millis_to_iso8601(millis: i64) -> Value {
+    OffsetDateTime::from_unix_timestamp_nanos(millis as i128 * 1_000_000)
+        .ok()
+        .and_then(|t| t.format(&Iso8601::DEFAULT).ok())
+        .into()
+}
+
+fn parse_json(text: Option<String>) -> Value {
+    text.and_then(|t| serde_json::from_str(&t).ok())
+        .unwrap_or(Value::Null)
+}
+
+/// Columns returned by [`row_to_job`].
+pub(crate) const JOB_COLUMNS: &str = "id, queue, kind, payload, headers, created_at, updated_at, \
+    scheduled_at, attempts, max_attempts, priority, unique_key, lease_time, error_reason";
+
+/// Convert a row selected with [`JOB_COLUMNS`] to the json representation of a job.
+pub(crate) fn row_to_job(row: &rusqlite::Row<'_>) -> rusqlite::Result<Value> {
+    Ok(json!({
+        "id": row.get::<_, String>(0)?,
+        "queue": row.get::<_, String>(1)?,
+        "kind": row.get::<_, String>(2)?,
+        "payload": parse_json(row.get(3)?),
+        "headers": parse_json(row.get(4)?),
+        "created_at": millis_to_iso8601(row.get(5)?),
+        "updated_at": millis_to_iso8601(row.get(6)?),
+        "scheduled_at": millis_to_iso8601(row.get(7)?),
+        "attempts": row.get::<_, u16>(8)?,
+        "max_attempts": row.get::<_, u16>(9)?,
+        "priority": row.get::<_, u8>(10)?,
+        "unique_key": row.get::<_, Option<String>>(11)?,
+        "lease_time": row.get::<_, u64>(12)?,
+        "error_reason": parse_json(row.get(13)?),
+        "workflow_id": null,
+        "batch_id": null,
+    }))
+}
@@ -0,0 +1,5 @@
+use mq::Error;
+
+pub(crate) fn convert_sqlite_error(err: rusqlite::Error) -> Error {
+    Error::OtherError(Box::new(err))
+}
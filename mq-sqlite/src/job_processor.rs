@@ -0,0 +1,125 @@
+use async_trait::async_trait;
+use mq::{Error, Job, JobProcessor};
+use rusqlite::OptionalExtension;
+use serde_json::Value;
+
+use crate::connection::{
+    now_millis, quote, row_to_job, with_connection, SqliteConnection, JOB_COLUMNS,
+};
+
+pub struct SqliteJobProcessor {
+    db: SqliteConnection,
+    table: String,
+}
+
+impl SqliteJobProcessor {
+    pub fn new<T: Into<String>>(db: SqliteConnection, table: T) -> Self {
+        Self {
+            db,
+            table: table.into(),
+        }
+    }
+
+    async fn delete(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error> {
+        let sql = format!(
+            "DELETE FROM {} WHERE id=?1 AND queue=?2 AND kind=?3",
+            quote(&self.table)
+        );
+        let (id, queue, kind) = (id.to_owned(), queue.to_owned(), kind.to_owned());
+
+        with_connection(&self.db, move |conn| {
+            conn.execute(&sql, (id, queue, kind))?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl JobProcessor for SqliteJobProcessor {
+    async fn poll_next_job(&self, queues: &[&str]) -> Result<Option<Job>, Error> {
+        let sql = format!(
+            r#"
+            UPDATE {table}
+            SET
+                attempts=attempts+1,
+                locked_at=?1,
+                updated_at=?1
+            WHERE id=(
+                SELECT id FROM {table}
+                WHERE
+                    attempts<max_attempts
+                    AND scheduled_at<=?1
+                    AND (locked_at IS NULL OR locked_at<?1-lease_time*1000)
+                    AND queue IN (SELECT value FROM json_each(?2))
+                ORDER BY priority DESC, updated_at ASC
+                LIMIT 1
+            )
+            RETURNING {columns}
+            "#,
+            table = quote(&self.table),
+            columns = JOB_COLUMNS,
+        );
+        let queues = serde_json::to_string(queues)?;
+
+        let job = with_connection(&self.db, move |conn| {
+            conn.query_row(&sql, (now_millis(), queues), row_to_job)
+                .optional()
+        })
+        .await?;
+
+        Ok(job.map(serde_json::from_value).transpose()?)
+    }
+
+    async fn complete_job_with_success(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<(), Error> {
+        self.delete(queue, kind, id).await
+    }
+
+    async fn complete_job_with_cancelled(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        _message: Option<String>,
+    ) -> Result<(), Error> {
+        self.delete(queue, kind, id).await
+    }
+
+    async fn fail_job(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        reason: Value,
+    ) -> Result<(), Error> {
+        let sql = format!(
+            r#"
+            UPDATE {}
+            SET
+                locked_at=NULL,
+                updated_at=?1,
+                error_reason=?2
+            WHERE id=?3 AND queue=?4 AND kind=?5
+            "#,
+            quote(&self.table)
+        );
+        let params = (
+            now_millis(),
+            reason.to_string(),
+            id.to_owned(),
+            queue.to_owned(),
+            kind.to_owned(),
+        );
+
+        with_connection(&self.db, move |conn| {
+            conn.execute(&sql, params)?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,10 @@
+mod connection;
+mod error;
+mod job_processor;
+mod migrations;
+mod producer;
+
+pub use connection::*;
+pub use job_processor::*;
+pub use migrations::*;
+pub use producer::*;
@@ -0,0 +1,82 @@
+use mq::Error;
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::{connection::quote, error::convert_sqlite_error};
+
+/// Migrations of the jobs table applied in order. `{table}` is replaced with the quoted table
+/// name and `{name}` with the raw one.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE {table} (
+        id           TEXT PRIMARY KEY NOT NULL,
+        queue        TEXT NOT NULL,
+        kind         TEXT NOT NULL,
+        payload      TEXT NOT NULL,
+        headers      TEXT NOT NULL DEFAULT '{}',
+        created_at   INTEGER NOT NULL,
+        updated_at   INTEGER NOT NULL,
+        scheduled_at INTEGER NOT NULL,
+        locked_at    INTEGER,
+        attempts     INTEGER NOT NULL,
+        max_attempts INTEGER NOT NULL,
+        priority     INTEGER NOT NULL,
+        unique_key   TEXT,
+        lease_time   INTEGER NOT NULL,
+        error_reason TEXT
+    );
+
+    CREATE INDEX "{name}_poll" ON {table} (queue, priority DESC, updated_at);
+    CREATE INDEX "{name}_unique_key" ON {table} (queue, kind, unique_key)
+        WHERE unique_key IS NOT NULL;
+"#,
+    r#"
+    -- rejects a second active job with the same queue, kind and unique_key. Fails on the
+    -- duplicates published before the index was unique, so they are cancelled by hand
+    DROP INDEX "{name}_unique_key";
+    CREATE UNIQUE INDEX "{name}_unique_key" ON {table} (queue, kind, unique_key)
+        WHERE unique_key IS NOT NULL AND attempts<max_attempts;
+"#,
+];
+
+/// Create or upgrade the jobs table. The applied migrations are tracked per table in
+/// `mq_migrations`.
+pub fn migrate(conn: &mut Connection, table: &str) -> Result<(), Error> {
+    let tx = conn.transaction().map_err(convert_sqlite_error)?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS mq_migrations (
+            table_name TEXT PRIMARY KEY NOT NULL,
+            version    INTEGER NOT NULL
+        )",
+        [],
+    )
+    .map_err(convert_sqlite_error)?;
+
+    let version: usize = tx
+        .query_row(
+            "SELECT version FROM mq_migrations WHERE table_name=?1",
+            [table],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(convert_sqlite_error)?
+        .unwrap_or(0);
+
+    for migration in MIGRATIONS.iter().skip(version) {
+        tx.execute_batch(
+            &migration
+                .replace("{table}", &quote(table))
+                .replace("{name}", &table.replace('"', "\"\"")),
+        )
+        .map_err(convert_sqlite_error)?;
+    }
+
+    tx.execute(
+        "INSERT INTO mq_migrations (table_name, version) VALUES (?1, ?2)
+        ON CONFLICT (table_name) DO UPDATE SET version=excluded.version",
+        (table, MIGRATIONS.len()),
+    )
+    .map_err(convert_sqlite_error)?;
+
+    tx.commit().map_err(convert_sqlite_error)
+}
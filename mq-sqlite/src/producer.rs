@@ -0,0 +1,255 @@
+use async_trait::async_trait;
+use mq::{Error, Job, Producer, PublishOutcome, QueueDepth, UniqueKeyConflict};
+use rusqlite::{OptionalExtension, Transaction};
+
+use crate::connection::{now_millis, quote, to_millis, with_connection, SqliteConnection};
+
+pub struct SqliteProducer {
+    db: SqliteConnection,
+    table: String,
+}
+
+impl SqliteProducer {
+    pub fn new<T: Into<String>>(db: SqliteConnection, table: T) -> Self {
+        Self {
+            db,
+            table: table.into(),
+        }
+    }
+}
+
+/// Id of the active job holding the unique key of the job, and whether it is leased.
+fn find_active(
+    tx: &Transaction,
+    table: &str,
+    job: &Job,
+    now: i64,
+) -> rusqlite::Result<Option<(String, bool)>> {
+    let Some(unique_key) = job.unique_key() else {
+        return Ok(None);
+    };
+
+    tx.query_row(
+        &format!(
+            r#"
+            SELECT id, locked_at IS NOT NULL AND locked_at>=?4-lease_time*1000
+            FROM {table}
+            WHERE
+                queue=?1
+                AND kind=?2
+                AND unique_key=?3
+                AND attempts<max_attempts
+            "#
+        ),
+        (job.queue(), job.kind(), unique_key, now),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+fn is_unique_violation(err: &rusqlite::Error) -> bool {
+    matches!(
+        err,
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE
+    )
+}
+
+/// Inserts the job, or applies its unique key conflict strategy to the active job holding its
+/// unique key. A leased job is not replaced or rescheduled, the job is skipped. `headers` are the
+/// serialized headers of the job.
+fn publish_job(
+    tx: &Transaction,
+    table: &str,
+    job: &Job,
+    headers: &str,
+    now: i64,
+) -> rusqlite::Result<PublishOutcome> {
+    let scheduled_at = job.scheduled_at().map(to_millis).unwrap_or(now);
+
+    let Some((id, leased)) = find_active(tx, table, job, now)? else {
+        let inserted = tx.execute(
+            &format!(
+                r#"
+                INSERT INTO {table} (
+                    id, queue, kind, payload, headers, created_at, updated_at, scheduled_at,
+                    locked_at, attempts, max_attempts, priority, unique_key, lease_time,
+                    error_reason
+                )
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7, NULL, ?8, ?9, ?10, ?11, ?12, NULL)
+                "#
+            ),
+            (
+                job.id(),
+                job.queue(),
+                job.kind(),
+                job.payload().to_string(),
+                headers,
+                now,
+                scheduled_at,
+                job.attempts(),
+                job.max_attempts(),
+                job.priority(),
+                job.unique_key(),
+                job.lease_time().as_secs(),
+            ),
+        );
+        return match inserted {
+            Ok(_) => Ok(PublishOutcome::Published),
+            // another connection published an active job with the unique key since find_active
+            Err(err) if is_unique_violation(&err) => match find_active(tx, table, job, now)? {
+                Some((id, _)) => Ok(PublishOutcome::Duplicate(id)),
+                None => Err(err),
+            },
+            Err(err) => Err(err),
+        };
+    };
+
+    match job.on_unique_key_conflict() {
+        _ if leased => Ok(PublishOutcome::Duplicate(id)),
+        UniqueKeyConflict::Replace => {
+            tx.execute(
+                &format!("UPDATE {table} SET payload=?2, scheduled_at=?3 WHERE id=?1"),
+                (&id, job.payload().to_string(), scheduled_at),
+            )?;
+            Ok(PublishOutcome::Replaced(id))
+        }
+        UniqueKeyConflict::Reschedule => {
+            tx.execute(
+                &format!("UPDATE {table} SET scheduled_at=?2 WHERE id=?1"),
+                (&id, scheduled_at),
+            )?;
+            Ok(PublishOutcome::Replaced(id))
+        }
+        UniqueKeyConflict::Skip | UniqueKeyConflict::Error => Ok(PublishOutcome::Duplicate(id)),
+    }
+}
+
+#[async_trait]
+impl Producer for SqliteProducer {
+    async fn publish(&self, job: Job) -> Result<PublishOutcome, Error> {
+        let outcome = self
+            .publish_many(vec![job])
+            .await?
+            .pop()
+            .expect("outcome of the published job");
+
+        Ok(outcome)
+    }
+
+    async fn exists(&self, queue: &str, kind: &str, id: &str) -> Result<bool, Error> {
+        let sql = format!(
+            "SELECT 1 FROM {} WHERE id=?1 AND queue=?2 AND kind=?3",
+            quote(&self.table)
+        );
+        let params = (id.to_owned(), queue.to_owned(), kind.to_owned());
+
+        with_connection(&self.db, move |conn| {
+            conn.query_row(&sql, params, |_| Ok(()))
+                .optional()
+                .map(|row| row.is_some())
+        })
+        .await
+    }
+
+    async fn cancel_by_id(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error> {
+        let sql = format!(
+            "DELETE FROM {} WHERE id=?1 AND queue=?2 AND kind=?3",
+            quote(&self.table)
+        );
+        let params = (id.to_owned(), queue.to_owned(), kind.to_owned());
+
+        with_connection(&self.db, move |conn| {
+            conn.execute(&sql, params)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn cancel_by_unique_key(&self, queue: &str, kind: &str, key: &str) -> Result<(), Error> {
+        let sql = format!(
+            "DELETE FROM {} WHERE queue=?1 AND kind=?2 AND unique_key=?3",
+            quote(&self.table)
+        );
+        let params = (queue.to_owned(), kind.to_owned(), key.to_owned());
+
+        with_connection(&self.db, move |conn| {
+            conn.execute(&sql, params)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn publish_many(&self, jobs: Vec<Job>) -> Result<Vec<PublishOutcome>, Error> {
+        if jobs.iter().any(|job| !job.depends_on().is_empty()) {
+            return Err(Error::NotSupported("depends_on".into()));
+        }
+        // compensations of sagas are chained with depends_on
+        if jobs.iter().any(|job| !job.saga_steps().is_empty()) {
+            return Err(Error::NotSupported("saga_steps".into()));
+        }
+
+        let jobs = jobs
+            .into_iter()
+            .map(|job| {
+                let job = job.with_trace_context();
+                let headers = serde_json::to_string(job.headers())?;
+                Ok((job, headers))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let table = quote(&self.table);
+        let now = now_millis();
+
+        with_connection(&self.db, move |conn| {
+            let tx = conn.transaction()?;
+
+            let outcomes = jobs
+                .iter()
+                .map(|(job, headers)| {
+                    let outcome = publish_job(&tx, &table, job, headers, now)?;
+                    Ok(outcome.check(job.on_unique_key_conflict()))
+                })
+                .collect::<rusqlite::Result<Result<Vec<_>, Error>>>()?;
+
+            // Nothing is published when any of the jobs fails.
+            if outcomes.is_ok() {
+                tx.commit()?;
+            }
+            Ok(outcomes)
+        })
+        .await?
+    }
+
+    async fn queue_depth(&self, queue: &str) -> Result<QueueDepth, Error> {
+        let sql = format!(
+            r#"
+            SELECT
+                COALESCE(SUM(NOT leased AND attempts<max_attempts AND scheduled_at<=?2), 0),
+                COALESCE(SUM(NOT leased AND attempts<max_attempts AND scheduled_at>?2), 0),
+                COALESCE(SUM(leased), 0),
+                COALESCE(SUM(NOT leased AND attempts>=max_attempts), 0)
+            FROM (
+                SELECT
+                    *,
+                    locked_at IS NOT NULL AND locked_at>=?2-lease_time*1000 AS leased
+                FROM {}
+                WHERE queue=?1
+            )
+            "#,
+            quote(&self.table)
+        );
+        let params = (queue.to_owned(), now_millis());
+
+        with_connection(&self.db, move |conn| {
+            conn.query_row(&sql, params, |row| {
+                Ok(QueueDepth {
+                    ready: row.get(0)?,
+                    scheduled: row.get(1)?,
+                    running: row.get(2)?,
+                    dead: row.get(3)?,
+                })
+            })
+        })
+        .await
+    }
+}
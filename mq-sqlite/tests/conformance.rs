@@ -0,0 +1,18 @@
+use std::sync::{Arc, Mutex};
+
+use mq_sqlite::{SqliteJobProcessor, SqliteProducer};
+use mq_testsuite::Backend;
+
+const TABLE: &str = "jobs";
+
+async fn backend() -> Backend {
+    let mut conn = mq_sqlite::open(":memory:").unwrap();
+    mq_sqlite::migrate(&mut conn, TABLE).unwrap();
+    let db = Arc::new(Mutex::new(conn));
+    Backend::new(
+        SqliteProducer::new(db.clone(), TABLE),
+        SqliteJobProcessor::new(db, TABLE),
+    )
+}
+
+mq_testsuite::backend_tests!(backend);
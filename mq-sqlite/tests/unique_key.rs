@@ -0,0 +1,40 @@
+const TABLE: &str = "jobs";
+
+const INSERT_JOB: &str = r#"
+    INSERT INTO jobs (
+        id, queue, kind, payload, created_at, updated_at, scheduled_at, attempts, max_attempts,
+        priority, unique_key, lease_time
+    )
+    VALUES (?1, 'default', 'job', '{}', 0, 0, 0, ?2, 3, 0, 'key', 60)
+"#;
+
+#[test]
+fn migration_fails_on_active_duplicates() {
+    let mut conn = mq_sqlite::open(":memory:").unwrap();
+    mq_sqlite::migrate(&mut conn, TABLE).unwrap();
+
+    // roll the table back to before the unique index, when duplicates could be published
+    conn.execute_batch(
+        r#"
+        DROP INDEX "jobs_unique_key";
+        CREATE INDEX "jobs_unique_key" ON jobs (queue, kind, unique_key)
+            WHERE unique_key IS NOT NULL;
+        UPDATE mq_migrations SET version=1 WHERE table_name='jobs';
+        "#,
+    )
+    .unwrap();
+    for (id, attempts) in [("first", 0), ("second", 1), ("dead", 3)] {
+        conn.execute(INSERT_JOB, (id, attempts)).unwrap();
+    }
+
+    let err = mq_sqlite::migrate(&mut conn, TABLE).unwrap_err();
+    assert!(
+        err.to_string().contains("UNIQUE constraint failed"),
+        "{err}"
+    );
+
+    conn.execute("DELETE FROM jobs WHERE id='second'", [])
+        .unwrap();
+    mq_sqlite::migrate(&mut conn, TABLE).unwrap();
+    conn.execute(INSERT_JOB, ("second", 0)).unwrap_err();
+}
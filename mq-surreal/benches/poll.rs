@@ -0,0 +1,169 @@
+//! Latency of `poll_next_job` with a large backlog of pending jobs.
+//!
+//! The number of jobs defaults to 1,000,000 and can be changed with `MQ_BENCH_JOBS`. The
+//! `delayed backlog` case polls a queue where `MQ_BENCH_DELAYED` jobs, 10,000 by default, are
+//! scheduled in the future with a higher priority than its only due job:
+//!
+//! ```sh
+//! MQ_BENCH_JOBS=100000 MQ_BENCH_DELAYED=1000 cargo bench -p mq-surreal --bench poll
+//! ```
+//!
+//! The polled job is released after each measured poll, so every iteration polls the same data.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mq::{Job, JobProcessor};
+use mq_surreal::SurrealJobProcessor;
+use surrealdb::{engine::any::Any, types::Datetime, Surreal};
+use tokio::runtime::Runtime;
+
+const TABLE: &str = "queue";
+const CHUNK: usize = 10_000;
+
+fn env(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Create `jobs` pending jobs spread over the `default` and `other` queues with a mix of
+/// priorities. One job in ten is scheduled in the future and one in a hundred is leased.
+async fn seed(db: &Surreal<Any>, jobs: usize, now: Datetime) {
+    for start in (0..jobs).step_by(CHUNK) {
+        db.query(
+            r#"
+            BEGIN TRANSACTION;
+
+            FOR $i IN $start..$end {
+                CREATE type::table($table)
+                SET
+                    created_at=$now,
+                    updated_at=$now+duration::from_millis($i),
+                    scheduled_at=IF $i%10=0 THEN $now+1d ELSE $now END,
+                    locked_at=IF $i%100=1 THEN $now END,
+                    queue=IF $i%2=0 THEN "default" ELSE "other" END,
+                    kind="bench",
+                    payload={ index: $i },
+                    attempts=0,
+                    max_attempts=3,
+                    priority=$i%3,
+                    lease_time=3600;
+            };
+
+            COMMIT TRANSACTION;
+            "#,
+        )
+        .bind(("table", TABLE))
+        .bind(("start", start as i64))
+        .bind(("end", (start + CHUNK).min(jobs) as i64))
+        .bind(("now", now))
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    }
+}
+
+/// Create `jobs` jobs of the `delayed` queue scheduled in the future with priority 9, followed
+/// by a single due job with priority 0.
+async fn seed_delayed(db: &Surreal<Any>, jobs: usize, now: Datetime) {
+    for start in (0..=jobs).step_by(CHUNK) {
+        db.query(
+            r#"
+            FOR $i IN $start..$end {
+                CREATE type::table($table)
+                SET
+                    created_at=$now,
+                    updated_at=$now+duration::from_millis($i),
+                    scheduled_at=IF $i<$jobs THEN $now+1d ELSE $now END,
+                    queue="delayed",
+                    kind="bench",
+                    payload={ index: $i },
+                    attempts=0,
+                    max_attempts=3,
+                    priority=IF $i<$jobs THEN 9 ELSE 0 END,
+                    lease_time=3600;
+            };
+            "#,
+        )
+        .bind(("table", TABLE))
+        .bind(("start", start as i64))
+        .bind(("end", (start + CHUNK).min(jobs + 1) as i64))
+        .bind(("jobs", jobs as i64))
+        .bind(("now", now))
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    }
+}
+
+/// Restore the polled job as seeded.
+async fn release(db: &Surreal<Any>, job: &Job, now: Datetime) {
+    db.query(
+        r#"
+        UPDATE type::record($table, $id)
+        SET
+            attempts=0,
+            locked_at=NONE,
+            updated_at=$now+duration::from_millis(payload.index);
+        "#,
+    )
+    .bind(("table", TABLE))
+    .bind(("id", job.id().to_owned()))
+    .bind(("now", now))
+    .await
+    .unwrap()
+    .check()
+    .unwrap();
+}
+
+fn poll(c: &mut Criterion) {
+    let jobs = env("MQ_BENCH_JOBS", 1_000_000);
+    let delayed = env("MQ_BENCH_DELAYED", 10_000);
+    let now = Datetime::now();
+
+    let rt = Runtime::new().unwrap();
+    let (db, job_processor) = rt.block_on(async {
+        let db = Arc::new(surrealdb::engine::any::connect("mem://").await.unwrap());
+        db.use_ns("mq").use_db("mq").await.unwrap();
+
+        let job_processor = SurrealJobProcessor::new(db.clone(), TABLE);
+        job_processor.ensure_schema().await.unwrap();
+        seed(&db, jobs, now).await;
+        seed_delayed(&db, delayed, now).await;
+        (db, job_processor)
+    });
+
+    let mut group = c.benchmark_group(format!("poll_next_job/{jobs}"));
+    for (name, queues) in [
+        ("one queue", &["default"][..]),
+        ("two queues", &["default", "other"][..]),
+        ("delayed backlog", &["delayed"][..]),
+    ] {
+        group.bench_function(name, |b| {
+            b.to_async(&rt).iter_custom(|iters| {
+                let (db, job_processor) = (&db, &job_processor);
+                async move {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let started = Instant::now();
+                        let job = job_processor.poll_next_job(queues).await.unwrap();
+                        elapsed += started.elapsed();
+                        release(db, &job.expect("a due job"), now).await;
+                    }
+                    elapsed
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, poll);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+/// Counts the jobs in `$finished` as `$outcome` in their batches and appends the callbacks of the
+/// batches that finished to `$jobs`. Batches are stored in `{$table}_batch`.
+/// Expects `$table`, `$finished`, `$outcome`, `$jobs` and `$now` to be bound.
+pub(crate) const FINISH_BATCHES_STATEMENT: &str = r#"
+    LET $batch_table = string::concat($table, "_batch");
+
+    FOR $finished_job IN $finished {
+        IF $finished_job.batch_id != NONE {
+            UPDATE type::record($batch_table, $finished_job.batch_id)
+            SET
+                succeeded += IF $outcome="succeeded" THEN 1 ELSE 0 END,
+                failed += IF $outcome="failed" THEN 1 ELSE 0 END,
+                cancelled += IF $outcome="cancelled" THEN 1 ELSE 0 END;
+        };
+    };
+
+    LET $callbacks = (
+        UPDATE type::table($batch_table)
+        SET finished_at=$now
+        WHERE
+            finished_at=NONE
+            AND record::id(id) IN $finished.batch_id
+            AND succeeded+failed+cancelled>=total
+        RETURN VALUE [
+            on_complete,
+            IF failed=0 AND cancelled=0 THEN on_success END,
+            IF failed>0 THEN on_failure END
+        ]
+    );
+
+    LET $jobs = array::concat(
+        $jobs,
+        array::filter(array::flatten($callbacks), |$callback| $callback != NONE)
+    );
+"#;
+
+/// Removes from `$finished` the jobs that exhausted their attempts before they were deleted, as
+/// they were already counted as failed, so cancelling a dead job does not count it twice.
+pub(crate) const SKIP_DEAD_JOBS_STATEMENT: &str = r#"
+    LET $finished = array::filter(
+        $finished,
+        |$job| $job.attempts<$job.max_attempts OR $job.locked_at!=NONE
+    );
+"#;
@@ -1,5 +1,20 @@
 use mq::Error;
+use surrealdb::{types::QueryError, IndexedResults};
 
 pub(crate) fn convert_surrealdb_error(err: surrealdb::Error) -> Error {
     Error::OtherError(Box::new(err))
 }
+
+/// Takes the error of the statement that failed the transaction of the query, rather than the
+/// errors of the statements that were not executed because of it. Returns None if no statement
+/// failed.
+pub(crate) fn take_transaction_error(result: &mut IndexedResults) -> Option<surrealdb::Error> {
+    let mut errors: Vec<_> = result.take_errors().into_iter().collect();
+    errors.sort_by_key(|(index, _)| *index);
+
+    let position = errors
+        .iter()
+        .position(|(_, err)| err.query_details() != Some(&QueryError::NotExecuted))
+        .unwrap_or_default();
+    (position < errors.len()).then(|| errors.swap_remove(position).1)
+}
@@ -0,0 +1,33 @@
+use mq::{Error, JobEvent};
+use serde_json::Value;
+
+/// Records a lifecycle event for each job in `$finished` in `{$table}_event` when `$events` is
+/// true. `$outcome` maps to the `succeeded`, `cancelled` and `dead_lettered` events.
+/// Expects `$table`, `$finished`, `$outcome` and `$now` to be bound. `$message` is recorded when
+/// bound.
+pub(crate) const RECORD_EVENTS_STATEMENT: &str = r#"
+    IF $events {
+        FOR $finished_job IN $finished {
+            CREATE type::table(string::concat($table, "_event"))
+            SET
+                at=$now,
+                queue=$finished_job.queue,
+                kind=$finished_job.kind,
+                job_id=record::id($finished_job.id),
+                event=IF $outcome="failed" THEN "dead_lettered" ELSE $outcome END,
+                error=IF $outcome="failed" THEN $finished_job.error_reason END,
+                message=IF $outcome="cancelled" THEN $message END;
+        };
+    };
+"#;
+
+pub(crate) fn value_to_job_event(mut val: Value) -> Result<JobEvent, Error> {
+    if let Some(object) = val.as_object_mut() {
+        object.remove("id");
+        if let Some(job_id) = object.remove("job_id") {
+            object.insert("id".into(), job_id);
+        }
+    }
+
+    Ok(serde_json::from_value(val)?)
+}
@@ -1,20 +1,61 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use mq::{Error, Job, JobProcessor};
-use serde_json::Value;
-use surrealdb::{engine::any::Any, types::Datetime, Surreal};
+use mq::{Error, Job, JobProcessor, Producer};
+use serde_json::{json, Value};
+use surrealdb::{engine::any::Any, Surreal};
 
-use crate::error::convert_surrealdb_error;
+use crate::{
+    batch::{FINISH_BATCHES_STATEMENT, SKIP_DEAD_JOBS_STATEMENT},
+    error::{convert_surrealdb_error, take_transaction_error},
+    event::RECORD_EVENTS_STATEMENT,
+    outcome::RECORD_OUTCOMES_STATEMENT,
+    publish::{NewJob, PUBLISH_JOBS_STATEMENT},
+    workflow::{fail_dependents_statement, RESOLVE_DEPENDENTS_STATEMENT},
+};
 
-fn surreal_value_to_job(val: surrealdb::types::Value) -> Result<Job, Error> {
+/// Index of the `RETURN $polled` statement in the query of `poll_next_job`, after
+/// `BEGIN TRANSACTION`, `LET $now`, `LET $candidates`, `LET $polled` and `IF $events`.
+const POLLED_JOB_INDEX: usize = 5;
+
+/// Index of the statement selecting the failed jobs in the query of `fail_expired_jobs`, after
+/// `BEGIN TRANSACTION`, `LET $now` and `LET $finished`.
+const EXPIRED_JOBS_INDEX: usize = 3;
+
+pub(crate) fn surreal_value_to_job(val: surrealdb::types::Value) -> Result<Job, Error> {
     let json_val = val.into_json_value();
     serde_json::from_value(json_val).map_err(|e| Error::OtherError(Box::new(e)))
 }
 
+/// Selects into `$candidates` the first claimable job of each of the `queues` bound as `$queue_0`,
+/// `$queue_1`, ... Each queue is read in polling order from the `{table}_poll` index with its own
+/// subquery, as subqueries inside closures do not use indexes.
+fn select_candidates_statement(queues: usize) -> String {
+    let selects = (0..queues)
+        .map(|index| {
+            format!(
+                r#",
+                (
+                    SELECT id, rank, updated_at
+                    FROM type::table($table)
+                    WHERE
+                        ready=true
+                        AND queue=$queue_{index}
+                        AND available_at<=$now
+                    ORDER BY rank ASC, updated_at ASC
+                    LIMIT 1
+                )"#
+            )
+        })
+        .collect::<String>();
+
+    format!("LET $candidates = array::concat([]{selects});")
+}
+
 pub struct SurrealJobProcessor {
     db: Arc<Surreal<Any>>,
     table: String,
+    events: bool,
 }
 
 impl SurrealJobProcessor {
@@ -22,6 +63,68 @@ impl SurrealJobProcessor {
         Self {
             db,
             table: table.into(),
+            events: false,
+        }
+    }
+
+    /// Record job lifecycle events in the `{table}_event` table.
+    pub fn with_events(mut self, events: bool) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn events(&self) -> bool {
+        self.events
+    }
+
+    /// Create or upgrade the schema of the jobs table, see [`migrate`](crate::migrate).
+    pub async fn ensure_schema(&self) -> Result<(), Error> {
+        crate::migrate(&self.db, &self.table).await
+    }
+
+    /// Delete the job, resolve its dependents, update its batch and publish the enqueued jobs in
+    /// one transaction.
+    async fn complete(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        output: Option<Value>,
+        jobs: Vec<Job>,
+    ) -> Result<(), Error> {
+        let mut result = self
+            .db
+            .query("BEGIN TRANSACTION;")
+            .query("LET $now = time::now();")
+            .query(
+                r#"
+            LET $finished = (
+                DELETE type::record($table, $id)
+                WHERE queue=$queue AND kind=$kind
+                RETURN BEFORE
+            );"#,
+            )
+            .query(RESOLVE_DEPENDENTS_STATEMENT)
+            .query(FINISH_BATCHES_STATEMENT)
+            .query(RECORD_OUTCOMES_STATEMENT)
+            .query(RECORD_EVENTS_STATEMENT)
+            .query(PUBLISH_JOBS_STATEMENT)
+            .query("COMMIT TRANSACTION;")
+            .bind(("table", self.table.clone()))
+            .bind(("events", self.events))
+            .bind(("id", id.to_owned()))
+            .bind(("queue", queue.to_owned()))
+            .bind(("kind", kind.to_owned()))
+            .bind(("jobs", jobs.iter().map(NewJob::from).collect::<Vec<_>>()))
+            .bind(("output", output))
+            .bind(("outcome", "succeeded"))
+            .await
+            .map_err(convert_surrealdb_error)?;
+
+        // The enqueued jobs fail the transaction e.g. when they depend on unknown jobs.
+        match take_transaction_error(&mut result) {
+            Some(err) => Err(convert_surrealdb_error(err)),
+            None => Ok(()),
         }
     }
 }
@@ -29,52 +132,62 @@ impl SurrealJobProcessor {
 #[async_trait]
 impl JobProcessor for SurrealJobProcessor {
     async fn poll_next_job(&self, queues: &[&str]) -> Result<Option<Job>, Error> {
-        let mut result = self
+        let mut query = self
             .db
+            .query("BEGIN TRANSACTION;")
+            .query("LET $now = time::now();")
+            .query(select_candidates_statement(queues.len()))
             .query(
                 r#"
-            UPDATE (
-                SELECT value id
-                FROM (
-                    SELECT * FROM type::table($table)
-                    WHERE
-                        attempts<max_attempts
-                        AND scheduled_at<=$now
-                        AND (
-                            locked_at=NONE
-                            OR (
-                                time::unix(locked_at)<time::unix($now)-lease_time
-                            )
-                        )
-                        AND queue IN $queues
-                    ORDER by priority DESC, updated_at ASC
+            LET $polled = (
+                UPDATE (
+                    SELECT VALUE id
+                    FROM $candidates
+                    ORDER BY rank ASC, updated_at ASC
                     LIMIT 1
                 )
+                SET
+                    attempts=attempts+1,
+                    locked_at=$now,
+                    updated_at=$now
+                WHERE
+                    ready=true
+                    AND available_at<=$now
+                RETURN
+                    record::id(id) as id,
+                    *
+            );
+
+            IF $events {
+                FOR $job IN $polled {
+                    CREATE type::table(string::concat($table, "_event"))
+                    SET
+                        at=$now,
+                        queue=$job.queue,
+                        kind=$job.kind,
+                        job_id=$job.id,
+                        event="started",
+                        attempt=$job.attempts;
+                };
+            };
+
+            RETURN $polled;"#,
             )
-            SET
-                attempts=attempts+1,
-                locked_at=$now,
-                updated_at=$now
-            RETURN
-                record::id(id) as id,
-                *"#,
-            )
+            .query("COMMIT TRANSACTION;")
             .bind(("table", self.table.clone()))
-            .bind((
-                "queues",
-                queues
-                    .into_iter()
-                    .map(|q| q.to_string())
-                    .collect::<Vec<String>>(),
-            ))
-            .bind(("now", Datetime::now()))
+            .bind(("events", self.events));
+        for (index, queue) in queues.iter().enumerate() {
+            query = query.bind((format!("queue_{index}"), queue.to_string()));
+        }
+
+        let mut result = query
             .await
             .map_err(convert_surrealdb_error)?
             .check()
             .map_err(convert_surrealdb_error)?;
 
         let job = result
-            .take::<Option<surrealdb::types::Value>>(0)
+            .take::<Option<surrealdb::types::Value>>(POLLED_JOB_INDEX)
             .map_err(convert_surrealdb_error)?
             .map(surreal_value_to_job)
             .transpose()?;
@@ -88,18 +201,30 @@ impl JobProcessor for SurrealJobProcessor {
         kind: &str,
         id: &str,
     ) -> Result<(), Error> {
-        self.db
-            .query(r#"DELETE type::record($table, $id) WHERE queue=$queue AND kind=$kind"#)
-            .bind(("table", self.table.to_owned()))
-            .bind(("id", id.to_owned()))
-            .bind(("queue", queue.to_owned()))
-            .bind(("kind", kind.to_owned()))
+        self.complete(queue, kind, id, None, Vec::new()).await
+    }
+
+    async fn complete_job_with_output(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        output: Value,
+    ) -> Result<(), Error> {
+        self.complete(queue, kind, id, Some(output), Vec::new())
             .await
-            .map_err(convert_surrealdb_error)?
-            .check()
-            .map_err(convert_surrealdb_error)?;
+    }
 
-        Ok(())
+    async fn complete_job_with_success_and_publish(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        output: Option<Value>,
+        jobs: Vec<Job>,
+        _producer: &dyn Producer,
+    ) -> Result<(), Error> {
+        self.complete(queue, kind, id, output, jobs).await
     }
 
     async fn complete_job_with_cancelled(
@@ -107,14 +232,34 @@ impl JobProcessor for SurrealJobProcessor {
         queue: &str,
         kind: &str,
         id: &str,
-        _message: Option<String>,
+        message: Option<String>,
     ) -> Result<(), Error> {
         self.db
-            .query(r#"DELETE type::record($table, $id) WHERE queue=$queue AND kind=$kind"#)
+            .query("BEGIN TRANSACTION;")
+            .query("LET $now = time::now();")
+            .query(
+                r#"
+            LET $finished = (
+                DELETE type::record($table, $id)
+                WHERE queue=$queue AND kind=$kind
+                RETURN BEFORE
+            );"#,
+            )
+            .query(SKIP_DEAD_JOBS_STATEMENT)
+            .query(FINISH_BATCHES_STATEMENT)
+            .query(RECORD_OUTCOMES_STATEMENT)
+            .query(RECORD_EVENTS_STATEMENT)
+            .query(PUBLISH_JOBS_STATEMENT)
+            .query(fail_dependents_statement())
+            .query("COMMIT TRANSACTION;")
             .bind(("table", self.table.clone()))
+            .bind(("events", self.events))
             .bind(("id", id.to_owned()))
             .bind(("queue", queue.to_owned()))
             .bind(("kind", kind.to_owned()))
+            .bind(("jobs", Vec::<NewJob>::new()))
+            .bind(("message", message))
+            .bind(("outcome", "cancelled"))
             .await
             .map_err(convert_surrealdb_error)?
             .check()
@@ -131,23 +276,51 @@ impl JobProcessor for SurrealJobProcessor {
         reason: Value,
     ) -> Result<(), Error> {
         self.db
+            .query("BEGIN TRANSACTION;")
+            .query("LET $now = time::now();")
             .query(
                 r#"
-            UPDATE type::record($table, $id)
-            SET
-                locked_at=NONE,
-                updated_at=$now,
-                error_reason=$error_reason
-            WHERE
-                queue=$queue AND kind=$kind
+            LET $finished = (
+                UPDATE type::record($table, $id)
+                SET
+                    locked_at=NONE,
+                    updated_at=$now,
+                    error_reason=$error_reason
+                WHERE
+                    queue=$queue AND kind=$kind
+            );
+
+            IF $events {
+                FOR $failed_job IN $finished {
+                    CREATE type::table(string::concat($table, "_event"))
+                    SET
+                        at=$now,
+                        queue=$failed_job.queue,
+                        kind=$failed_job.kind,
+                        job_id=record::id($failed_job.id),
+                        event="failed",
+                        attempt=$failed_job.attempts,
+                        error=$error_reason;
+                };
+            };
+
+            LET $finished = array::filter($finished, |$job| $job.attempts>=$job.max_attempts);
             "#,
             )
+            .query(FINISH_BATCHES_STATEMENT)
+            .query(RECORD_OUTCOMES_STATEMENT)
+            .query(RECORD_EVENTS_STATEMENT)
+            .query(PUBLISH_JOBS_STATEMENT)
+            .query(fail_dependents_statement())
+            .query("COMMIT TRANSACTION;")
             .bind(("table", self.table.clone()))
+            .bind(("events", self.events))
             .bind(("id", id.to_owned()))
             .bind(("queue", queue.to_owned()))
             .bind(("kind", kind.to_owned()))
             .bind(("error_reason", reason))
-            .bind(("now", Datetime::now()))
+            .bind(("jobs", Vec::<NewJob>::new()))
+            .bind(("outcome", "failed"))
             .await
             .map_err(convert_surrealdb_error)?
             .check()
@@ -155,4 +328,72 @@ impl JobProcessor for SurrealJobProcessor {
 
         Ok(())
     }
+
+    async fn fail_expired_jobs(&self, queues: &[&str]) -> Result<Vec<Job>, Error> {
+        let mut result = self
+            .db
+            .query("BEGIN TRANSACTION;")
+            .query("LET $now = time::now();")
+            .query(
+                r#"
+            LET $finished = (
+                UPDATE type::table($table)
+                SET
+                    locked_at=NONE,
+                    updated_at=$now,
+                    error_reason=$error_reason
+                WHERE
+                    last_attempt=true
+                    -- queue IN $queues is planned on the unique key index and misses jobs
+                    AND $queues CONTAINS queue
+                    AND available_at<=$now
+            );"#,
+            )
+            .query("SELECT record::id(id) AS id, * FROM $finished;")
+            .query(
+                r#"
+            IF $events {
+                FOR $failed_job IN $finished {
+                    CREATE type::table(string::concat($table, "_event"))
+                    SET
+                        at=$now,
+                        queue=$failed_job.queue,
+                        kind=$failed_job.kind,
+                        job_id=record::id($failed_job.id),
+                        event="failed",
+                        attempt=$failed_job.attempts,
+                        error=$error_reason;
+                };
+            };"#,
+            )
+            .query(FINISH_BATCHES_STATEMENT)
+            .query(RECORD_OUTCOMES_STATEMENT)
+            .query(RECORD_EVENTS_STATEMENT)
+            .query(PUBLISH_JOBS_STATEMENT)
+            .query(fail_dependents_statement())
+            .query("COMMIT TRANSACTION;")
+            .bind(("table", self.table.clone()))
+            .bind(("events", self.events))
+            .bind((
+                "queues",
+                queues
+                    .iter()
+                    .map(|queue| queue.to_string())
+                    .collect::<Vec<_>>(),
+            ))
+            .bind(("error_reason", json!({ "error": "lease expired" })))
+            .bind(("jobs", Vec::<NewJob>::new()))
+            .bind(("outcome", "failed"))
+            .await
+            .map_err(convert_surrealdb_error)?
+            .check()
+            .map_err(convert_surrealdb_error)?;
+
+        result
+            .take::<Vec<surrealdb::types::Value>>(EXPIRED_JOBS_INDEX)
+            .map_err(convert_surrealdb_error)?
+            .into_iter()
+            .map(surreal_value_to_job)
+            .collect()
+    }
 }
@@ -0,0 +1,304 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use mq::{Error, Job, JobFilter, JobPage, JobState, JobStore, Page};
+use serde_json::Value;
+use surrealdb::{engine::any::Any, method::Query, Surreal};
+use time::OffsetDateTime;
+
+use crate::{
+    error::convert_surrealdb_error, job_processor::surreal_value_to_job, publish::to_datetime,
+};
+
+/// Times the payload is read and patched again when it changed concurrently before
+/// `patch_payload` gives up.
+const PATCH_PAYLOAD_ATTEMPTS: usize = 10;
+
+/// Jobs whose lease expired or that were never leased.
+const NOT_LEASED: &str = "(locked_at=NONE OR time::unix(locked_at)<time::unix($now)-lease_time)";
+
+/// Condition selecting the jobs in the state, as counted by `queue_depth`.
+fn state_condition(state: JobState) -> String {
+    match state {
+        JobState::Ready => format!(
+            "{NOT_LEASED} AND attempts<max_attempts AND scheduled_at<=$now \
+             AND (depends_on=NONE OR depends_on=[])"
+        ),
+        JobState::Scheduled => format!(
+            "{NOT_LEASED} AND attempts<max_attempts \
+             AND (scheduled_at>$now OR (depends_on!=NONE AND depends_on!=[]))"
+        ),
+        JobState::Running => {
+            "locked_at!=NONE AND time::unix(locked_at)>=time::unix($now)-lease_time".to_owned()
+        }
+        JobState::Dead => format!("attempts>=max_attempts AND {NOT_LEASED}"),
+    }
+}
+
+/// Condition selecting the jobs matching the filter, with the parameters bound by
+/// [`bind_filter`].
+fn filter_condition(filter: &JobFilter) -> String {
+    let mut conditions = Vec::new();
+    if filter.queue().is_some() {
+        conditions.push("queue=$queue".to_owned());
+    }
+    if filter.kind().is_some() {
+        conditions.push("kind=$kind".to_owned());
+    }
+    if let Some(state) = filter.state() {
+        conditions.push(format!("({})", state_condition(state)));
+    }
+    if filter.unique_key().is_some() {
+        conditions.push("unique_key=$unique_key".to_owned());
+    }
+    if filter.scheduled_after().is_some() {
+        conditions.push("scheduled_at>=$scheduled_after".to_owned());
+    }
+    if filter.scheduled_before().is_some() {
+        conditions.push("scheduled_at<$scheduled_before".to_owned());
+    }
+    if filter.min_attempts().is_some() {
+        conditions.push("attempts>=$min_attempts".to_owned());
+    }
+    if filter.max_attempts().is_some() {
+        conditions.push("attempts<=$max_attempts".to_owned());
+    }
+    match filter.has_error() {
+        Some(true) => conditions.push("error_reason!=NONE".to_owned()),
+        Some(false) => conditions.push("error_reason=NONE".to_owned()),
+        None => {}
+    }
+    if !filter.headers().is_empty() {
+        conditions.push(
+            "array::all(object::entries($headers), |$header| $this.headers[$header[0]]=$header[1])"
+                .to_owned(),
+        );
+    }
+
+    if conditions.is_empty() {
+        "true".to_owned()
+    } else {
+        conditions.join(" AND ")
+    }
+}
+
+fn bind_filter<'r>(query: Query<'r, Any>, filter: &JobFilter) -> Query<'r, Any> {
+    query
+        .bind(("queue", filter.queue().clone()))
+        .bind(("kind", filter.kind().clone()))
+        .bind(("unique_key", filter.unique_key().clone()))
+        .bind(("scheduled_after", filter.scheduled_after().map(to_datetime)))
+        .bind((
+            "scheduled_before",
+            filter.scheduled_before().map(to_datetime),
+        ))
+        .bind(("min_attempts", filter.min_attempts()))
+        .bind(("max_attempts", filter.max_attempts()))
+        .bind(("headers", filter.headers().clone()))
+}
+
+pub struct SurrealJobStore {
+    db: Arc<Surreal<Any>>,
+    table: String,
+}
+
+impl SurrealJobStore {
+    pub fn new<T: Into<String>>(db: Arc<Surreal<Any>>, table: T) -> Self {
+        Self {
+            db,
+            table: table.into(),
+        }
+    }
+
+    /// Query updating the job matching the condition with the `SET` clause. Fails when it makes
+    /// a dead job active again while another active job holds its unique key.
+    fn update_query(&self, id: &str, set: &str, condition: &str) -> Query<'_, Any> {
+        self.db
+            .query("LET $now = time::now();")
+            .query(format!(
+                r#"
+            UPDATE type::record($table, $id)
+            SET {set}, updated_at=$now
+            WHERE {condition}
+            RETURN record::id(id) AS id, *;"#
+            ))
+            .bind(("table", self.table.clone()))
+            .bind(("id", id.to_owned()))
+    }
+}
+
+/// Runs the query of [`SurrealJobStore::update_query`], returning the updated job.
+async fn update_job(query: Query<'_, Any>) -> Result<Option<Job>, Error> {
+    let mut result = query
+        .await
+        .map_err(convert_surrealdb_error)?
+        .check()
+        .map_err(convert_surrealdb_error)?;
+
+    result
+        .take::<Option<surrealdb::types::Value>>(1)
+        .map_err(convert_surrealdb_error)?
+        .map(surreal_value_to_job)
+        .transpose()
+}
+
+#[async_trait]
+impl JobStore for SurrealJobStore {
+    async fn get_job(&self, id: &str) -> Result<Option<Job>, Error> {
+        let mut result = self
+            .db
+            .query("SELECT record::id(id) AS id, * FROM type::record($table, $id);")
+            .bind(("table", self.table.clone()))
+            .bind(("id", id.to_owned()))
+            .await
+            .map_err(convert_surrealdb_error)?
+            .check()
+            .map_err(convert_surrealdb_error)?;
+
+        result
+            .take::<Option<surrealdb::types::Value>>(0)
+            .map_err(convert_surrealdb_error)?
+            .map(surreal_value_to_job)
+            .transpose()
+    }
+
+    async fn list_jobs(&self, filter: &JobFilter, page: &Page) -> Result<JobPage, Error> {
+        let mut condition = filter_condition(filter);
+        if page.cursor().is_some() {
+            condition.push_str(" AND id>type::record($table, $cursor)");
+        }
+
+        // One more job than the limit is selected to know whether there is a next page.
+        let query = self
+            .db
+            .query("LET $now = time::now();")
+            .query(format!(
+                r#"
+            SELECT record::id(id) AS id, *
+            FROM type::table($table)
+            WHERE {condition}
+            ORDER BY id
+            LIMIT $limit;"#
+            ))
+            .bind(("table", self.table.clone()))
+            .bind(("cursor", page.cursor().clone()))
+            .bind(("limit", page.limit() + 1));
+        let mut result = bind_filter(query, filter)
+            .await
+            .map_err(convert_surrealdb_error)?
+            .check()
+            .map_err(convert_surrealdb_error)?;
+
+        let mut jobs = result
+            .take::<Vec<surrealdb::types::Value>>(1)
+            .map_err(convert_surrealdb_error)?
+            .into_iter()
+            .map(surreal_value_to_job)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let next_cursor = if jobs.len() > page.limit() {
+            jobs.truncate(page.limit());
+            jobs.last().map(|job| job.id().to_owned())
+        } else {
+            None
+        };
+
+        Ok(JobPage { jobs, next_cursor })
+    }
+
+    async fn count(&self, filter: &JobFilter) -> Result<u64, Error> {
+        let query = self
+            .db
+            .query("LET $now = time::now();")
+            .query(format!(
+                r#"
+            RETURN (
+                SELECT count() AS count
+                FROM type::table($table)
+                WHERE {}
+                GROUP ALL
+            )[0].count ?? 0;"#,
+                filter_condition(filter)
+            ))
+            .bind(("table", self.table.clone()));
+        let mut result = bind_filter(query, filter)
+            .await
+            .map_err(convert_surrealdb_error)?
+            .check()
+            .map_err(convert_surrealdb_error)?;
+
+        let count: Option<u64> = result.take(1).map_err(convert_surrealdb_error)?;
+        Ok(count.unwrap_or_default())
+    }
+
+    async fn retry_now(&self, id: &str, reset_attempts: bool) -> Result<Option<Job>, Error> {
+        let set = if reset_attempts {
+            "scheduled_at=$now, attempts=0"
+        } else {
+            "scheduled_at=$now"
+        };
+        update_job(self.update_query(id, set, "true")).await
+    }
+
+    async fn reschedule(
+        &self,
+        id: &str,
+        scheduled_at: OffsetDateTime,
+    ) -> Result<Option<Job>, Error> {
+        let query = self
+            .update_query(id, "scheduled_at=$scheduled_at", "true")
+            .bind(("scheduled_at", to_datetime(scheduled_at)));
+        update_job(query).await
+    }
+
+    async fn set_priority(&self, id: &str, priority: u8) -> Result<Option<Job>, Error> {
+        let query = self
+            .update_query(id, "priority=$priority", "true")
+            .bind(("priority", priority));
+        update_job(query).await
+    }
+
+    async fn set_max_attempts(&self, id: &str, max_attempts: u16) -> Result<Option<Job>, Error> {
+        let query = self
+            .update_query(id, "max_attempts=$max_attempts", "true")
+            .bind(("max_attempts", max_attempts));
+        update_job(query).await
+    }
+
+    /// Only object patches are supported, as a scalar or array patch replaces the whole payload
+    /// and payloads are stored as objects.
+    async fn patch_payload(&self, id: &str, patch: Value) -> Result<Option<Job>, Error> {
+        if !patch.is_object() {
+            return Err(Error::NotSupported(
+                "patch_payload with a non-object patch".into(),
+            ));
+        }
+
+        // The payload is only updated if it did not change since it was read, otherwise the
+        // patch is applied again to the new payload.
+        for _ in 0..PATCH_PAYLOAD_ATTEMPTS {
+            let Some(job) = self.get_job(id).await? else {
+                return Ok(None);
+            };
+
+            let mut payload = job.payload().clone();
+            mq::merge_patch(&mut payload, &patch);
+
+            let query = self
+                .update_query(id, "payload=$payload", "payload=$previous")
+                .bind(("payload", payload))
+                .bind(("previous", job.payload().clone()));
+            if let Some(job) = update_job(query).await? {
+                return Ok(Some(job));
+            }
+        }
+
+        Err(Error::UnknownError(format!(
+            "payload of job {id} changed concurrently {PATCH_PAYLOAD_ATTEMPTS} times"
+        )))
+    }
+
+    async fn release_lock(&self, id: &str) -> Result<Option<Job>, Error> {
+        update_job(self.update_query(id, "locked_at=NONE", "true")).await
+    }
+}
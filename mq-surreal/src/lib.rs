@@ -1,6 +1,16 @@
+mod batch;
 mod error;
+mod event;
 mod job_processor;
+mod job_store;
+mod migrations;
+mod outcome;
 mod producer;
+mod publish;
+mod workflow;
 
 pub use job_processor::*;
+pub use job_store::*;
+pub use migrations::*;
 pub use producer::*;
+pub use publish::take_publish_outcomes;
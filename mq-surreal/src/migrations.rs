@@ -0,0 +1,189 @@
+use mq::Error;
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::error::{convert_surrealdb_error, take_transaction_error};
+
+/// Migrations of the jobs table applied in order. `{table}`, `{batch}`, `{outcome}` and `{event}`
+/// are replaced with the quoted names of the jobs table and of its `_batch`, `_outcome` and
+/// `_event` tables, and `{name}` with the raw name of the jobs table.
+const MIGRATIONS: &[&str] = &[
+    r#"
+    DEFINE TABLE IF NOT EXISTS {table} SCHEMAFULL;
+    DEFINE FIELD IF NOT EXISTS created_at     ON {table} TYPE datetime;
+    DEFINE FIELD IF NOT EXISTS updated_at     ON {table} TYPE datetime;
+    DEFINE FIELD IF NOT EXISTS scheduled_at   ON {table} TYPE datetime;
+    DEFINE FIELD IF NOT EXISTS locked_at      ON {table} TYPE option<datetime>;
+    DEFINE FIELD IF NOT EXISTS queue          ON {table} TYPE string;
+    DEFINE FIELD IF NOT EXISTS kind           ON {table} TYPE string;
+    DEFINE FIELD IF NOT EXISTS max_attempts   ON {table} TYPE number;
+    DEFINE FIELD IF NOT EXISTS attempts       ON {table} TYPE number;
+    DEFINE FIELD IF NOT EXISTS priority       ON {table} TYPE number;
+    DEFINE FIELD IF NOT EXISTS unique_key     ON {table} TYPE option<string>;
+    DEFINE FIELD IF NOT EXISTS lease_time     ON {table} TYPE number;
+    DEFINE FIELD IF NOT EXISTS payload        ON {table} TYPE object FLEXIBLE;
+    DEFINE FIELD IF NOT EXISTS error_reason   ON {table} TYPE option<object> FLEXIBLE;
+    DEFINE FIELD IF NOT EXISTS workflow_id    ON {table} TYPE option<string>;
+    DEFINE FIELD IF NOT EXISTS batch_id       ON {table} TYPE option<string>;
+    DEFINE FIELD IF NOT EXISTS depends_on     ON {table} TYPE option<array<string>>;
+    DEFINE FIELD IF NOT EXISTS on_dependency_failure ON {table} TYPE option<string>;
+    DEFINE FIELD IF NOT EXISTS dependency_outputs ON {table} TYPE option<object> FLEXIBLE;
+    DEFINE FIELD IF NOT EXISTS saga_steps     ON {table} TYPE option<array<object>> FLEXIBLE;
+    DEFINE FIELD IF NOT EXISTS track_outcome  ON {table} TYPE option<bool>;
+    DEFINE FIELD IF NOT EXISTS headers        ON {table} TYPE option<object> FLEXIBLE;
+    DEFINE INDEX IF NOT EXISTS `{name}_unique_key` ON {table} FIELDS queue, kind, unique_key;
+
+    DEFINE TABLE IF NOT EXISTS {batch} SCHEMAFULL;
+    DEFINE FIELD IF NOT EXISTS created_at     ON {batch} TYPE datetime;
+    DEFINE FIELD IF NOT EXISTS finished_at    ON {batch} TYPE option<datetime>;
+    DEFINE FIELD IF NOT EXISTS total          ON {batch} TYPE number;
+    DEFINE FIELD IF NOT EXISTS succeeded      ON {batch} TYPE number;
+    DEFINE FIELD IF NOT EXISTS failed         ON {batch} TYPE number;
+    DEFINE FIELD IF NOT EXISTS cancelled      ON {batch} TYPE number;
+    DEFINE FIELD IF NOT EXISTS on_complete    ON {batch} TYPE option<object> FLEXIBLE;
+    DEFINE FIELD IF NOT EXISTS on_success     ON {batch} TYPE option<object> FLEXIBLE;
+    DEFINE FIELD IF NOT EXISTS on_failure     ON {batch} TYPE option<object> FLEXIBLE;
+
+    DEFINE TABLE IF NOT EXISTS {outcome} SCHEMAFULL;
+    DEFINE FIELD IF NOT EXISTS finished_at    ON {outcome} TYPE datetime;
+    DEFINE FIELD IF NOT EXISTS queue          ON {outcome} TYPE string;
+    DEFINE FIELD IF NOT EXISTS kind           ON {outcome} TYPE string;
+    DEFINE FIELD IF NOT EXISTS outcome        ON {outcome} TYPE string;
+    DEFINE FIELD IF NOT EXISTS output         ON {outcome} TYPE any;
+    DEFINE FIELD IF NOT EXISTS message        ON {outcome} TYPE option<string>;
+    DEFINE FIELD IF NOT EXISTS error_reason   ON {outcome} TYPE option<object> FLEXIBLE;
+
+    DEFINE TABLE IF NOT EXISTS {event} SCHEMAFULL;
+    DEFINE FIELD IF NOT EXISTS at             ON {event} TYPE datetime;
+    DEFINE FIELD IF NOT EXISTS queue          ON {event} TYPE string;
+    DEFINE FIELD IF NOT EXISTS kind           ON {event} TYPE string;
+    DEFINE FIELD IF NOT EXISTS job_id         ON {event} TYPE string;
+    DEFINE FIELD IF NOT EXISTS event          ON {event} TYPE string;
+    DEFINE FIELD IF NOT EXISTS attempt        ON {event} TYPE option<number>;
+    DEFINE FIELD IF NOT EXISTS error          ON {event} TYPE option<object> FLEXIBLE;
+    DEFINE FIELD IF NOT EXISTS message        ON {event} TYPE option<string>;
+"#,
+    r#"
+    -- fields computed on every write so poll_next_job can scan the claimable jobs of a queue
+    -- in polling order: ready jobs are claimable once available_at has passed, rank sorts higher
+    -- priorities first in ascending order
+    DEFINE FIELD IF NOT EXISTS ready          ON {table} TYPE bool
+        VALUE attempts<max_attempts AND (depends_on=NONE OR depends_on=[]);
+    DEFINE FIELD IF NOT EXISTS available_at   ON {table} TYPE datetime
+        VALUE IF locked_at=NONE THEN scheduled_at ELSE locked_at+duration::from_secs(lease_time) END;
+    DEFINE FIELD IF NOT EXISTS rank           ON {table} TYPE number VALUE -priority;
+    UPDATE {table};
+    DEFINE INDEX IF NOT EXISTS `{name}_poll` ON {table} FIELDS ready, queue, rank, updated_at;
+"#,
+    r#"
+    -- active is only set while a job with a unique_key has attempts left, so the unique index
+    -- rejects a second active job with the same queue, kind and unique_key
+    DEFINE FIELD IF NOT EXISTS active         ON {table} TYPE option<bool>
+        VALUE IF unique_key!=NONE AND attempts<max_attempts THEN true END;
+    UPDATE {table};
+
+    -- fails on the duplicates published before the index existed, so they are cancelled by hand
+    LET $duplicates = (
+        SELECT * FROM (
+            SELECT
+                queue, kind, unique_key,
+                array::sort(array::group(record::id(id))) AS ids,
+                count() AS jobs
+            FROM {table}
+            WHERE active=true
+            GROUP BY queue, kind, unique_key
+        )
+        WHERE jobs>1
+    );
+    IF $duplicates != [] {
+        THROW string::concat(
+            "active jobs share a unique_key, cancel all but one of each: ",
+            array::join(
+                array::map($duplicates, |$duplicate| string::concat(
+                    $duplicate.queue, " ", $duplicate.kind, " ", $duplicate.unique_key,
+                    " [", array::join($duplicate.ids, ", "), "]"
+                )),
+                "; "
+            )
+        );
+    };
+
+    REMOVE INDEX IF EXISTS `{name}_unique_key` ON {table};
+    DEFINE INDEX IF NOT EXISTS `{name}_active_unique_key` ON {table}
+        FIELDS queue, kind, unique_key, active UNIQUE;
+"#,
+    r#"
+    -- marks the dependents cancelled because a dependency failed until they are deleted, within
+    -- the transaction failing the dependency
+    DEFINE FIELD IF NOT EXISTS dependency_failed ON {table} TYPE option<bool>;
+    DEFINE INDEX IF NOT EXISTS `{name}_dependency_failed` ON {table} FIELDS dependency_failed;
+"#,
+    r#"
+    -- last_attempt is only set while a job runs its last attempt, so fail_expired_jobs finds the
+    -- jobs whose last lease expired without scanning the table
+    DEFINE FIELD IF NOT EXISTS last_attempt   ON {table} TYPE option<bool>
+        VALUE IF locked_at!=NONE AND attempts>=max_attempts THEN true END;
+    UPDATE {table};
+    DEFINE INDEX IF NOT EXISTS `{name}_last_attempt` ON {table} FIELDS last_attempt;
+"#,
+    r#"
+    -- outcomes are purged by age
+    DEFINE INDEX IF NOT EXISTS `{name}_outcome_finished_at` ON {outcome} FIELDS finished_at;
+"#,
+    r#"
+    -- events are purged by age
+    DEFINE INDEX IF NOT EXISTS `{name}_event_at` ON {event} FIELDS at;
+"#,
+    r#"
+    -- indexes every dependency of a job, so the dependents of a finished job are found without
+    -- scanning the table
+    DEFINE INDEX IF NOT EXISTS `{name}_depends_on` ON {table} FIELDS depends_on[*];
+"#,
+];
+
+/// Quote the table name so it can be interpolated in statements.
+fn quote(table: &str) -> String {
+    format!("`{}`", table.replace('\\', "\\\\").replace('`', "\\`"))
+}
+
+fn render(migration: &str, table: &str) -> String {
+    migration
+        .replace("{table}", &quote(table))
+        .replace("{batch}", &quote(&format!("{table}_batch")))
+        .replace("{outcome}", &quote(&format!("{table}_outcome")))
+        .replace("{event}", &quote(&format!("{table}_event")))
+        .replace("{name}", &table.replace('\\', "\\\\").replace('`', "\\`"))
+}
+
+/// Create or upgrade the jobs table and the tables named after it. The applied migrations are
+/// tracked per table in `mq_migrations`.
+pub async fn migrate(db: &Surreal<Any>, table: &str) -> Result<(), Error> {
+    let mut result = db
+        .query("DEFINE TABLE IF NOT EXISTS mq_migrations SCHEMALESS;")
+        .query("SELECT VALUE version FROM ONLY type::record('mq_migrations', $table);")
+        .bind(("table", table.to_owned()))
+        .await
+        .map_err(convert_surrealdb_error)?
+        .check()
+        .map_err(convert_surrealdb_error)?;
+    let version: Option<i64> = result.take(1).map_err(convert_surrealdb_error)?;
+    let version = version.unwrap_or(0) as usize;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let mut result = db
+            .query("BEGIN TRANSACTION;")
+            .query(render(migration, table))
+            .query(
+                "UPSERT type::record('mq_migrations', $table) SET table_name=$table, version=$version;",
+            )
+            .query("COMMIT TRANSACTION;")
+            .bind(("table", table.to_owned()))
+            .bind(("version", index as i64 + 1))
+            .await
+            .map_err(convert_surrealdb_error)?;
+        if let Some(err) = take_transaction_error(&mut result) {
+            return Err(convert_surrealdb_error(err));
+        }
+    }
+
+    Ok(())
+}
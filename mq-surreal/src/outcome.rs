@@ -0,0 +1,37 @@
+use mq::{Error, JobOutcome};
+use serde_json::Value;
+
+/// Keeps the outcome of the jobs in `$finished` that track it in `{$table}_outcome`.
+/// Expects `$table`, `$finished`, `$outcome` and `$now` to be bound. `$output` and `$message`
+/// are recorded when bound.
+pub(crate) const RECORD_OUTCOMES_STATEMENT: &str = r#"
+    FOR $finished_job IN $finished {
+        IF $finished_job.track_outcome {
+            UPSERT type::record(string::concat($table, "_outcome"), record::id($finished_job.id))
+            SET
+                finished_at=$now,
+                queue=$finished_job.queue,
+                kind=$finished_job.kind,
+                outcome=$outcome,
+                output=$output,
+                message=$message,
+                error_reason=IF $outcome="failed" THEN $finished_job.error_reason END;
+        };
+    };
+"#;
+
+pub(crate) fn value_to_job_outcome(val: Value) -> Result<JobOutcome, Error> {
+    let field = |name: &str| val.get(name).filter(|v| !v.is_null()).cloned();
+
+    match val.get("outcome").and_then(Value::as_str) {
+        Some("succeeded") => Ok(JobOutcome::Succeeded(field("output"))),
+        Some("cancelled") => Ok(JobOutcome::Cancelled(
+            field("message").and_then(|m| m.as_str().map(str::to_owned)),
+        )),
+        Some("failed") => Ok(JobOutcome::Failed(field("error_reason"))),
+        outcome => Err(Error::UnknownError(format!(
+            "unknown job outcome {:?}",
+            outcome
+        ))),
+    }
+}
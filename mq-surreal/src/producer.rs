@@ -1,14 +1,43 @@
-use std::sync::Arc;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use async_trait::async_trait;
-use mq::{Error, Job, Producer};
-use surrealdb::{engine::any::Any, types::Datetime, Surreal};
+use futures::{StreamExt, TryStreamExt};
+use mq::{
+    Batch, BatchProgress, Error, Job, JobEventFilter, JobEventStream, JobOutcome, Producer,
+    PublishOutcome, QueueDepth, Workflow, WorkflowProgress,
+};
+use surrealdb::{engine::any::Any, method::Query, types::Action, Connection, Surreal};
 
-use crate::error::convert_surrealdb_error;
+use crate::{
+    batch::{FINISH_BATCHES_STATEMENT, SKIP_DEAD_JOBS_STATEMENT},
+    error::convert_surrealdb_error,
+    event::{value_to_job_event, RECORD_EVENTS_STATEMENT},
+    outcome::{value_to_job_outcome, RECORD_OUTCOMES_STATEMENT},
+    publish::{
+        publish_block_statement, retry_unique_key_conflict, take_publish_outcomes, NewJob,
+        PublishParams, PUBLISH_JOBS_STATEMENT, RETURN_PUBLISH_OUTCOMES_STATEMENT,
+    },
+    workflow::fail_dependents_statement,
+};
+
+/// Suffix of the parameter bound by [`SurrealProducer::publish_with`], unique per call so it can
+/// be called more than once for the same query.
+static PUBLISH_WITH_PARAMS: AtomicU64 = AtomicU64::new(0);
+
+/// Index of [`RETURN_PUBLISH_OUTCOMES_STATEMENT`] in the query of `publish_many`, after
+/// `BEGIN TRANSACTION`, `LET $now` and [`PUBLISH_JOBS_STATEMENT`].
+const PUBLISH_OUTCOMES_INDEX: usize = 3;
 
 pub struct SurrealProducer {
     db: Arc<Surreal<Any>>,
     table: String,
+    events: bool,
 }
 
 impl SurrealProducer {
@@ -16,79 +45,95 @@ impl SurrealProducer {
         Self {
             db,
             table: table.into(),
+            events: false,
         }
     }
-}
 
-#[async_trait]
-impl Producer for SurrealProducer {
-    async fn publish(&self, job: Job) -> Result<(), Error> {
-        let now = Datetime::now();
+    /// Record job lifecycle events in the `{table}_event` table.
+    pub fn with_events(mut self, events: bool) -> Self {
+        self.events = events;
+        self
+    }
 
-        self.db
-            .query(
+    pub fn events(&self) -> bool {
+        self.events
+    }
+
+    /// Add a statement publishing the jobs to the query of the caller, so they are only published
+    /// if its transaction commits, e.g. a query between `BEGIN TRANSACTION` and
+    /// `COMMIT TRANSACTION` or a query of a transaction started with `Surreal::begin`. The
+    /// statement returns the outcome of each job, read with [`take_publish_outcomes`].
+    ///
+    /// ```ignore
+    /// let query = db
+    ///     .query("BEGIN TRANSACTION;")
+    ///     .query("CREATE order SET total=$total;")
+    ///     .bind(("total", 42));
+    /// let mut response = producer
+    ///     .publish_with(query, vec![job])
+    ///     .query("COMMIT TRANSACTION;")
+    ///     .await?;
+    /// let outcomes = mq_surreal::take_publish_outcomes(&mut response, 2)?;
+    /// ```
+    ///
+    /// Unlike [`Producer::publish_many`], publishing is not retried when a job with the same
+    /// `unique_key` is published concurrently, the transaction fails instead.
+    pub fn publish_with<'r, C: Connection>(
+        &self,
+        query: Query<'r, C>,
+        jobs: Vec<Job>,
+    ) -> Query<'r, C> {
+        let param = format!(
+            "mq_publish_{}",
+            PUBLISH_WITH_PARAMS.fetch_add(1, Ordering::Relaxed)
+        );
+        let jobs: Vec<NewJob> = jobs.iter().map(NewJob::from).collect();
+
+        query.query(publish_block_statement(&param)).bind((
+            param,
+            PublishParams {
+                table: self.table.clone(),
+                events: self.events,
+                jobs,
+            },
+        ))
+    }
+
+    /// Delete the rows of the table with `suffix` whose `field` is more than `older_than` ago.
+    async fn purge(&self, suffix: &str, field: &str, older_than: Duration) -> Result<u64, Error> {
+        let mut result = self
+            .db
+            .query(format!(
                 r#"
-                BEGIN TRANSACTION;
-
-                LET $allow = IF $unique_key == NONE THEN
-                    true
-                ELSE
-                    count((
-                        SELECT * FROM queue
-                        WHERE
-                            queue=$queue
-                            AND kind=$kind
-                            AND unique_key=$unique_key
-                            AND attempts<max_attempts
-                    )) == 0
-                END;
-
-                IF $allow THEN
-                    CREATE type::record($table, $id)
-                    SET created_at=$now,
-                        updated_at=$now,
-                        scheduled_at=$scheduled_at,
-                        locked_at=NONE,
-                        queue=$queue,
-                        kind=$kind,
-                        payload=$payload,
-                        attempts=$attempts,
-                        max_attempts=$max_attempts,
-                        priority=$priority,
-                        unique_key=$unique_key,
-                        lease_time=$lease_time,
-                        error_reason=NONE;
-                END;
-
-                COMMIT TRANSACTION;
-                "#,
-            )
-            .bind(("table", self.table.clone()))
-            .bind(("id", job.id().to_owned()))
-            .bind(("queue", job.queue().to_owned()))
-            .bind(("kind", job.kind().to_owned()))
-            .bind(("payload", job.payload().to_owned()))
-            .bind(("now", now))
-            .bind(("unique_key", job.unique_key().to_owned()))
-            .bind((
-                "scheduled_at",
-                job.scheduled_at()
-                    .map(|t| {
-                        Datetime::from_timestamp(t.unix_timestamp(), t.nanosecond())
-                            .expect("valid timestamp")
-                    })
-                    .unwrap_or_else(Datetime::now),
+            array::len((
+                DELETE type::table(string::concat($table, $suffix))
+                WHERE {field}<time::now()-duration::from_millis($older_than)
+                RETURN BEFORE
+            ));"#
             ))
-            .bind(("attempts", job.attempts()))
-            .bind(("max_attempts", job.max_attempts()))
-            .bind(("priority", job.priority()))
-            .bind(("lease_time", job.lease_time().as_secs()))
+            .bind(("table", self.table.clone()))
+            .bind(("suffix", suffix.to_owned()))
+            .bind(("older_than", older_than.as_millis() as u64))
             .await
             .map_err(convert_surrealdb_error)?
             .check()
             .map_err(convert_surrealdb_error)?;
 
-        Ok(())
+        let deleted: Option<u64> = result.take(0).map_err(convert_surrealdb_error)?;
+        Ok(deleted.unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl Producer for SurrealProducer {
+    async fn publish(&self, job: Job) -> Result<PublishOutcome, Error> {
+        let outcome = self
+            .publish_many(vec![job])
+            .await?
+            .pop()
+            .expect("outcome of the published job");
+
+        Ok(outcome)
     }
 
     async fn exists(&self, queue: &str, kind: &str, id: &str) -> Result<bool, Error> {
@@ -114,11 +159,30 @@ impl Producer for SurrealProducer {
 
     async fn cancel_by_id(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error> {
         self.db
-            .query(r#"DELETE type::record($table, $id) WHERE queue=$queue AND kind=$kind"#)
+            .query("BEGIN TRANSACTION;")
+            .query("LET $now = time::now();")
+            .query(
+                r#"
+            LET $finished = (
+                DELETE type::record($table, $id)
+                WHERE queue=$queue AND kind=$kind
+                RETURN BEFORE
+            );"#,
+            )
+            .query(SKIP_DEAD_JOBS_STATEMENT)
+            .query(FINISH_BATCHES_STATEMENT)
+            .query(RECORD_OUTCOMES_STATEMENT)
+            .query(RECORD_EVENTS_STATEMENT)
+            .query(PUBLISH_JOBS_STATEMENT)
+            .query(fail_dependents_statement())
+            .query("COMMIT TRANSACTION;")
             .bind(("table", self.table.clone()))
+            .bind(("events", self.events))
             .bind(("id", id.to_owned()))
             .bind(("queue", queue.to_owned()))
             .bind(("kind", kind.to_owned()))
+            .bind(("jobs", Vec::<NewJob>::new()))
+            .bind(("outcome", "cancelled"))
             .await
             .map_err(convert_surrealdb_error)?
             .check()
@@ -129,11 +193,30 @@ impl Producer for SurrealProducer {
 
     async fn cancel_by_unique_key(&self, queue: &str, kind: &str, key: &str) -> Result<(), Error> {
         self.db
-            .query(r#"DELETE type::table($table) WHERE queue=$queue AND kind=$kind AND unique_key=$key"#)
+            .query("BEGIN TRANSACTION;")
+            .query("LET $now = time::now();")
+            .query(
+                r#"
+            LET $finished = (
+                DELETE type::table($table)
+                WHERE queue=$queue AND kind=$kind AND unique_key=$key
+                RETURN BEFORE
+            );"#,
+            )
+            .query(SKIP_DEAD_JOBS_STATEMENT)
+            .query(FINISH_BATCHES_STATEMENT)
+            .query(RECORD_OUTCOMES_STATEMENT)
+            .query(RECORD_EVENTS_STATEMENT)
+            .query(PUBLISH_JOBS_STATEMENT)
+            .query(fail_dependents_statement())
+            .query("COMMIT TRANSACTION;")
             .bind(("table", self.table.clone()))
+            .bind(("events", self.events))
             .bind(("queue", queue.to_owned()))
             .bind(("kind", kind.to_owned()))
             .bind(("key", key.to_owned()))
+            .bind(("jobs", Vec::<NewJob>::new()))
+            .bind(("outcome", "cancelled"))
             .await
             .map_err(convert_surrealdb_error)?
             .check()
@@ -141,4 +224,287 @@ impl Producer for SurrealProducer {
 
         Ok(())
     }
+
+    async fn publish_many(&self, jobs: Vec<Job>) -> Result<Vec<PublishOutcome>, Error> {
+        let jobs: Vec<NewJob> = jobs.iter().map(NewJob::from).collect();
+
+        let mut result = retry_unique_key_conflict(&self.table, || async {
+            self.db
+                .query("BEGIN TRANSACTION;")
+                .query("LET $now = time::now();")
+                .query(PUBLISH_JOBS_STATEMENT)
+                .query(RETURN_PUBLISH_OUTCOMES_STATEMENT)
+                .query("COMMIT TRANSACTION;")
+                .bind(("table", self.table.clone()))
+                .bind(("events", self.events))
+                .bind(("jobs", jobs.clone()))
+                .await
+        })
+        .await?;
+
+        take_publish_outcomes(&mut result, PUBLISH_OUTCOMES_INDEX)
+    }
+
+    async fn publish_workflow(&self, workflow: Workflow) -> Result<(), Error> {
+        let jobs = workflow.jobs().iter().map(NewJob::from).collect::<Vec<_>>();
+
+        retry_unique_key_conflict(&self.table, || async {
+            self.db
+                .query("BEGIN TRANSACTION;")
+                .query("LET $now = time::now();")
+                .query(PUBLISH_JOBS_STATEMENT)
+                .query("COMMIT TRANSACTION;")
+                .bind(("table", self.table.clone()))
+                .bind(("events", self.events))
+                .bind(("jobs", jobs.clone()))
+                .await
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn workflow_progress(&self, workflow_id: &str) -> Result<WorkflowProgress, Error> {
+        let mut result = self
+            .db
+            .query("LET $now = time::now();")
+            .query(
+                r#"
+            SELECT
+                count(
+                    depends_on!=NONE AND depends_on!=[]
+                ) AS waiting,
+                count(
+                    (depends_on=NONE OR depends_on=[])
+                    AND attempts<max_attempts
+                    AND (locked_at=NONE OR time::unix(locked_at)<time::unix($now)-lease_time)
+                ) AS ready,
+                count(
+                    locked_at!=NONE AND time::unix(locked_at)>=time::unix($now)-lease_time
+                ) AS running,
+                count(
+                    attempts>=max_attempts
+                    AND (locked_at=NONE OR time::unix(locked_at)<time::unix($now)-lease_time)
+                ) AS failed
+            FROM type::table($table)
+            WHERE workflow_id=$workflow_id
+            GROUP ALL
+            "#,
+            )
+            .bind(("table", self.table.clone()))
+            .bind(("workflow_id", workflow_id.to_owned()))
+            .await
+            .map_err(convert_surrealdb_error)?
+            .check()
+            .map_err(convert_surrealdb_error)?;
+
+        let progress = result
+            .take::<Option<surrealdb::types::Value>>(1)
+            .map_err(convert_surrealdb_error)?
+            .map(|val| serde_json::from_value(val.into_json_value()))
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(progress)
+    }
+
+    async fn queue_depth(&self, queue: &str) -> Result<QueueDepth, Error> {
+        let mut result = self
+            .db
+            .query("LET $now = time::now();")
+            .query(
+                r#"
+            SELECT
+                count(
+                    (locked_at=NONE OR time::unix(locked_at)<time::unix($now)-lease_time)
+                    AND attempts<max_attempts
+                    AND scheduled_at<=$now
+                    AND (depends_on=NONE OR depends_on=[])
+                ) AS ready,
+                count(
+                    (locked_at=NONE OR time::unix(locked_at)<time::unix($now)-lease_time)
+                    AND attempts<max_attempts
+                    AND (scheduled_at>$now OR (depends_on!=NONE AND depends_on!=[]))
+                ) AS scheduled,
+                count(
+                    locked_at!=NONE AND time::unix(locked_at)>=time::unix($now)-lease_time
+                ) AS running,
+                count(
+                    attempts>=max_attempts
+                    AND (locked_at=NONE OR time::unix(locked_at)<time::unix($now)-lease_time)
+                ) AS dead
+            FROM type::table($table)
+            WHERE queue=$queue
+            GROUP ALL
+            "#,
+            )
+            .bind(("table", self.table.clone()))
+            .bind(("queue", queue.to_owned()))
+            .await
+            .map_err(convert_surrealdb_error)?
+            .check()
+            .map_err(convert_surrealdb_error)?;
+
+        let depth = result
+            .take::<Option<surrealdb::types::Value>>(1)
+            .map_err(convert_surrealdb_error)?
+            .map(|val| serde_json::from_value(val.into_json_value()))
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(depth)
+    }
+
+    async fn publish_batch(&self, batch: Batch) -> Result<(), Error> {
+        let jobs = batch.jobs().iter().map(NewJob::from).collect::<Vec<_>>();
+        let on_complete = batch.on_complete().as_ref().map(NewJob::from);
+        let on_success = batch.on_success().as_ref().map(NewJob::from);
+        let on_failure = batch.on_failure().as_ref().map(NewJob::from);
+
+        retry_unique_key_conflict(&self.table, || async {
+            self.db
+                .query("BEGIN TRANSACTION;")
+                .query("LET $now = time::now();")
+                .query(
+                    r#"
+                LET $batch_table = string::concat($table, "_batch");
+
+                CREATE type::record($batch_table, $batch_id)
+                SET created_at=$now,
+                    finished_at=NONE,
+                    total=0,
+                    succeeded=0,
+                    failed=0,
+                    cancelled=0,
+                    on_complete=$on_complete,
+                    on_success=$on_success,
+                    on_failure=$on_failure;
+                "#,
+                )
+                .query(PUBLISH_JOBS_STATEMENT)
+                .query(
+                    r#"
+                -- jobs skipped because of their unique_key are not part of the batch
+                UPDATE type::record($batch_table, $batch_id)
+                SET total=array::len(
+                    array::filter($jobs.id, |$id| record::exists(type::record($table, $id)))
+                );
+
+                -- finishes an empty batch right away
+                LET $finished = [{ batch_id: $batch_id }];
+                LET $jobs = [];
+                "#,
+                )
+                .query(FINISH_BATCHES_STATEMENT)
+                .query(PUBLISH_JOBS_STATEMENT)
+                .query("COMMIT TRANSACTION;")
+                .bind(("table", self.table.clone()))
+                .bind(("events", self.events))
+                .bind(("batch_id", batch.id().to_owned()))
+                .bind(("jobs", jobs.clone()))
+                .bind(("on_complete", on_complete.clone()))
+                .bind(("on_success", on_success.clone()))
+                .bind(("on_failure", on_failure.clone()))
+                .bind(("outcome", None::<String>))
+                .await
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn batch_progress(&self, batch_id: &str) -> Result<BatchProgress, Error> {
+        let mut result = self
+            .db
+            .query(
+                r#"
+            SELECT
+                total,
+                succeeded,
+                failed,
+                cancelled,
+                finished_at!=NONE AS finished
+            FROM type::record(string::concat($table, "_batch"), $batch_id)
+            "#,
+            )
+            .bind(("table", self.table.clone()))
+            .bind(("batch_id", batch_id.to_owned()))
+            .await
+            .map_err(convert_surrealdb_error)?
+            .check()
+            .map_err(convert_surrealdb_error)?;
+
+        let progress = result
+            .take::<Option<surrealdb::types::Value>>(0)
+            .map_err(convert_surrealdb_error)?
+            .map(|val| serde_json::from_value(val.into_json_value()))
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(progress)
+    }
+
+    async fn job_outcome(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<Option<JobOutcome>, Error> {
+        let mut result = self
+            .db
+            .query(
+                r#"
+            SELECT outcome, output, message, error_reason
+            FROM type::record(string::concat($table, "_outcome"), $id)
+            WHERE queue=$queue AND kind=$kind
+            "#,
+            )
+            .bind(("table", self.table.clone()))
+            .bind(("id", id.to_owned()))
+            .bind(("queue", queue.to_owned()))
+            .bind(("kind", kind.to_owned()))
+            .await
+            .map_err(convert_surrealdb_error)?
+            .check()
+            .map_err(convert_surrealdb_error)?;
+
+        result
+            .take::<Option<surrealdb::types::Value>>(0)
+            .map_err(convert_surrealdb_error)?
+            .map(|val| value_to_job_outcome(val.into_json_value()))
+            .transpose()
+    }
+
+    async fn purge_outcomes(&self, older_than: Duration) -> Result<u64, Error> {
+        self.purge("_outcome", "finished_at", older_than).await
+    }
+
+    async fn subscribe(&self, filter: JobEventFilter) -> Result<JobEventStream, Error> {
+        let mut result = self
+            .db
+            .query("LIVE SELECT * FROM type::table(string::concat($table, \"_event\"))")
+            .bind(("table", self.table.clone()))
+            .await
+            .map_err(convert_surrealdb_error)?
+            .check()
+            .map_err(convert_surrealdb_error)?;
+
+        let stream = result
+            .stream::<surrealdb::types::Value>(0)
+            .map_err(convert_surrealdb_error)?
+            .map_err(convert_surrealdb_error)
+            .try_filter_map(|notification| async move {
+                if notification.action != Action::Create {
+                    return Ok(None);
+                }
+                value_to_job_event(notification.data.into_json_value()).map(Some)
+            })
+            .try_filter(move |event| futures::future::ready(filter.matches(event)));
+
+        Ok(stream.boxed())
+    }
+
+    async fn purge_events(&self, older_than: Duration) -> Result<u64, Error> {
+        self.purge("_event", "at", older_than).await
+    }
 }
@@ -0,0 +1,312 @@
+use std::{future::Future, time::Duration};
+
+use mq::{Error, Job, PublishOutcome};
+use surrealdb::{
+    types::{Datetime, QueryError, SurrealValue},
+    IndexedResults,
+};
+use time::OffsetDateTime;
+
+use crate::error::{convert_surrealdb_error, take_transaction_error};
+
+/// Job as it is bound to [`PUBLISH_JOBS_STATEMENT`].
+#[derive(Clone, SurrealValue)]
+#[surreal(crate = "surrealdb::types")]
+pub(crate) struct NewJob {
+    id: String,
+    queue: String,
+    kind: String,
+    payload: serde_json::Value,
+    scheduled_at: Option<Datetime>,
+    schedule_in: Option<u64>,
+    attempts: u16,
+    max_attempts: u16,
+    priority: u8,
+    unique_key: Option<String>,
+    lease_time: u64,
+    workflow_id: Option<String>,
+    batch_id: Option<String>,
+    depends_on: Vec<String>,
+    on_dependency_failure: String,
+    on_unique_key_conflict: String,
+    saga_steps: serde_json::Value,
+    track_outcome: bool,
+    headers: serde_json::Value,
+}
+
+pub(crate) fn to_datetime(date_time: OffsetDateTime) -> Datetime {
+    Datetime::from_timestamp(date_time.unix_timestamp(), date_time.nanosecond())
+        .expect("valid timestamp")
+}
+
+/// Object bound to the parameter of [`publish_block_statement`].
+#[derive(SurrealValue)]
+#[surreal(crate = "surrealdb::types")]
+pub(crate) struct PublishParams {
+    pub(crate) table: String,
+    pub(crate) events: bool,
+    pub(crate) jobs: Vec<NewJob>,
+}
+
+impl From<&Job> for NewJob {
+    fn from(job: &Job) -> Self {
+        let mut headers = job.headers().clone();
+        mq::inject_trace_context(&mut headers);
+
+        Self {
+            id: job.id().to_owned(),
+            queue: job.queue().to_owned(),
+            kind: job.kind().to_owned(),
+            payload: job.payload().to_owned(),
+            scheduled_at: job.scheduled_at().map(to_datetime),
+            schedule_in: job.schedule_in().map(|d| d.as_millis() as u64),
+            attempts: job.attempts(),
+            max_attempts: job.max_attempts(),
+            priority: job.priority(),
+            unique_key: job.unique_key().to_owned(),
+            lease_time: job.lease_time().as_secs(),
+            workflow_id: job.workflow_id().to_owned(),
+            batch_id: job.batch_id().to_owned(),
+            depends_on: job.depends_on().to_owned(),
+            on_dependency_failure: job.on_dependency_failure().as_str().to_owned(),
+            on_unique_key_conflict: job.on_unique_key_conflict().as_str().to_owned(),
+            saga_steps: serde_json::to_value(job.saga_steps()).expect("serializable saga steps"),
+            track_outcome: job.track_outcome(),
+            headers: serde_json::to_value(headers).expect("serializable headers"),
+        }
+    }
+}
+
+/// Creates every job in `$jobs` in `$table`. A job whose `unique_key` is held by an active job is
+/// skipped, replaces or reschedules that job, or fails the transaction with a [`DUPLICATE_JOB`]
+/// error, according to its `on_unique_key_conflict`. A leased job is not replaced or rescheduled,
+/// the job is skipped instead. A dependency that does not exist and is not published in the same
+/// `$jobs` is dropped from `depends_on`, as completed jobs are deleted. Records a `published` event when `$events` is true. Jobs scheduled with
+/// `with_schedule_in` are scheduled relative to `$now`.
+/// Expects `$table`, `$jobs` and `$now` to be bound.
+pub(crate) const PUBLISH_JOBS_STATEMENT: &str = r#"
+    FOR $job IN $jobs {
+        LET $existing = IF $job.unique_key != NONE {
+            (
+                SELECT record::id(id) AS id, locked_at!=NONE AND available_at>$now AS leased
+                FROM type::table($table)
+                WHERE
+                    queue=$job.queue
+                    AND kind=$job.kind
+                    AND unique_key=$job.unique_key
+                    AND active=true
+                LIMIT 1
+            )[0]
+        };
+
+        LET $scheduled_at = IF $job.schedule_in != NONE THEN
+            $now+duration::from_millis($job.schedule_in)
+        ELSE
+            $job.scheduled_at ?? $now
+        END;
+
+        IF $existing == NONE {
+            LET $depends_on = array::filter(
+                $job.depends_on,
+                |$dependency| record::exists(type::record($table, $dependency))
+                    OR $dependency IN $jobs.id
+            );
+
+            CREATE type::record($table, $job.id)
+            SET created_at=$now,
+                updated_at=$now,
+                scheduled_at=$scheduled_at,
+                locked_at=NONE,
+                queue=$job.queue,
+                kind=$job.kind,
+                payload=$job.payload,
+                attempts=$job.attempts,
+                max_attempts=$job.max_attempts,
+                priority=$job.priority,
+                unique_key=$job.unique_key,
+                lease_time=$job.lease_time,
+                error_reason=NONE,
+                workflow_id=$job.workflow_id,
+                batch_id=$job.batch_id,
+                depends_on=$depends_on,
+                on_dependency_failure=$job.on_dependency_failure,
+                dependency_outputs={},
+                saga_steps=$job.saga_steps,
+                track_outcome=$job.track_outcome,
+                headers=$job.headers;
+
+            IF $events {
+                CREATE type::table(string::concat($table, "_event"))
+                SET
+                    at=$now,
+                    queue=$job.queue,
+                    kind=$job.kind,
+                    job_id=$job.id,
+                    event="published";
+            };
+        } ELSE IF $job.on_unique_key_conflict == "replace" AND !$existing.leased {
+            UPDATE type::record($table, $existing.id)
+            SET payload=$job.payload, scheduled_at=$scheduled_at;
+        } ELSE IF $job.on_unique_key_conflict == "reschedule" AND !$existing.leased {
+            UPDATE type::record($table, $existing.id)
+            SET scheduled_at=$scheduled_at;
+        } ELSE IF $job.on_unique_key_conflict == "error" {
+            THROW string::concat("duplicate job ", $existing.id);
+        };
+    };
+"#;
+
+/// Returns the outcome of each job in `$jobs` after [`PUBLISH_JOBS_STATEMENT`], as an object with
+/// the `outcome` and the `id` of the existing job it conflicted with, read with
+/// [`take_publish_outcomes`]. It is an expression rather than a `RETURN` statement, which would
+/// skip the remaining statements of the transaction.
+pub(crate) const RETURN_PUBLISH_OUTCOMES_STATEMENT: &str = r#"
+    array::map($jobs, |$job| IF record::exists(type::record($table, $job.id)) {
+        { outcome: "published" }
+    } ELSE {
+        LET $existing = (
+            SELECT record::id(id) AS id, locked_at!=NONE AND available_at>$now AS leased
+            FROM type::table($table)
+            WHERE
+                queue=$job.queue
+                AND kind=$job.kind
+                AND unique_key=$job.unique_key
+                AND active=true
+            LIMIT 1
+        )[0];
+        {
+            outcome: IF $job.on_unique_key_conflict IN ["replace", "reschedule"]
+                AND !$existing.leased
+            THEN
+                "replaced"
+            ELSE
+                "duplicate"
+            END,
+            id: $existing.id
+        }
+    });
+"#;
+
+/// Times publishing is attempted when it conflicts with a job with the same `unique_key`
+/// published concurrently.
+const PUBLISH_ATTEMPTS: u32 = 5;
+
+/// Backoff before the first retry of a conflicting publish, doubled on every retry.
+const PUBLISH_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Prefix of the error thrown by [`PUBLISH_JOBS_STATEMENT`], followed by the id of the existing
+/// job.
+const DUPLICATE_JOB: &str = "duplicate job ";
+
+/// Publishes the jobs of the object bound to `$param` as a single block statement returning their
+/// outcomes, so it can be added to a query of the caller. The object has the `table`, `events`
+/// and `jobs` fields. Parameters defined in the block do not leak to the following statements.
+pub(crate) fn publish_block_statement(param: &str) -> String {
+    format!(
+        r#"{{
+    LET $table = ${param}.table;
+    LET $events = ${param}.events;
+    LET $jobs = ${param}.jobs;
+    LET $now = time::now();
+    {PUBLISH_JOBS_STATEMENT}
+    {RETURN_PUBLISH_OUTCOMES_STATEMENT}
+}};"#
+    )
+}
+
+/// Takes the outcomes of the jobs published by the statement at `index` of the response, i.e. the
+/// statement added by [`SurrealProducer::publish_with`](crate::SurrealProducer::publish_with).
+/// Returns [`Error::DuplicateJob`] when a job conflicted with an active job with the same
+/// `unique_key` and was published with the `Error` strategy.
+pub fn take_publish_outcomes(
+    result: &mut IndexedResults,
+    index: usize,
+) -> Result<Vec<PublishOutcome>, Error> {
+    let outcomes: Vec<surrealdb::types::Value> =
+        result
+            .take(index)
+            .map_err(|err| match duplicate_job_id(&err) {
+                Some(id) => Error::DuplicateJob(id),
+                None => convert_surrealdb_error(err),
+            })?;
+
+    outcomes
+        .into_iter()
+        .map(|outcome| {
+            let outcome = outcome.into_json_value();
+            let id = || {
+                outcome
+                    .get("id")
+                    .and_then(|id| id.as_str())
+                    .unwrap_or_default()
+                    .to_owned()
+            };
+
+            match outcome.get("outcome").and_then(|o| o.as_str()) {
+                Some("published") => Ok(PublishOutcome::Published),
+                Some("duplicate") => Ok(PublishOutcome::Duplicate(id())),
+                Some("replaced") => Ok(PublishOutcome::Replaced(id())),
+                outcome => Err(Error::UnknownError(format!(
+                    "unknown publish outcome {:?}",
+                    outcome
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// Id of the existing job in a [`DUPLICATE_JOB`] error.
+fn duplicate_job_id(err: &surrealdb::Error) -> Option<String> {
+    if !err.is_thrown() {
+        return None;
+    }
+    let (_, id) = err.message().split_once(DUPLICATE_JOB)?;
+    Some(id.to_owned())
+}
+
+/// Whether the transaction failed because a job with the same `unique_key` was published
+/// concurrently: either it conflicted with the other transaction, or it found the job in the
+/// `{table}_active_unique_key` index, which SurrealDB only reports as an internal error naming the
+/// index.
+fn is_unique_key_conflict(err: &surrealdb::Error, table: &str) -> bool {
+    err.query_details() == Some(&QueryError::TransactionConflict)
+        || (err.is_internal()
+            && err
+                .message()
+                .contains(&format!("index `{table}_active_unique_key` ")))
+}
+
+/// Runs `publish` and checks its results. A job with the same `unique_key` published
+/// concurrently makes the transaction fail, in which case `publish` runs again after a jittered
+/// backoff, so the job is handled as a conflict with the job that was published, up to
+/// [`PUBLISH_ATTEMPTS`] times before the conflict is returned. A [`DUPLICATE_JOB`] error is
+/// returned as [`Error::DuplicateJob`].
+pub(crate) async fn retry_unique_key_conflict<F, Fut>(
+    table: &str,
+    publish: F,
+) -> Result<IndexedResults, Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = surrealdb::Result<IndexedResults>>,
+{
+    let mut attempt = 1;
+    loop {
+        let mut result = publish().await.map_err(convert_surrealdb_error)?;
+        let Some(err) = take_transaction_error(&mut result) else {
+            return Ok(result);
+        };
+
+        if attempt < PUBLISH_ATTEMPTS && is_unique_key_conflict(&err, table) {
+            // jittered up to twice the backoff, so concurrent publishes do not retry in lockstep
+            let backoff = PUBLISH_BACKOFF * 2u32.pow(attempt - 1);
+            tokio::time::sleep(backoff.mul_f64(fastrand::f64() + 1.0)).await;
+            attempt += 1;
+            continue;
+        }
+
+        return Err(match duplicate_job_id(&err) {
+            Some(id) => Error::DuplicateJob(id),
+            None => convert_surrealdb_error(err),
+        });
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{
+    batch::FINISH_BATCHES_STATEMENT, event::RECORD_EVENTS_STATEMENT,
+    outcome::RECORD_OUTCOMES_STATEMENT, publish::PUBLISH_JOBS_STATEMENT,
+};
+
+/// Removes `$id` from the dependencies of its dependents and records `$output` for them.
+/// Expects `$table`, `$id` and `$output` to be bound.
+pub(crate) const RESOLVE_DEPENDENTS_STATEMENT: &str = r#"
+    UPDATE type::table($table)
+    SET
+        depends_on=array::complement(depends_on, [$id]),
+        dependency_outputs=object::extend(
+            dependency_outputs ?? {},
+            object::from_entries([[$id, $output]])
+        )
+    WHERE $id IN depends_on;
+"#;
+
+/// Levels of dependents failed by [`fail_dependents_statement`] in one transaction.
+const MAX_DEPENDENT_LEVELS: usize = 1000;
+
+/// Applies the dependency failure policy to the dependents of the jobs in `$finished`, which were
+/// cancelled or exhausted their attempts, in the transaction that finished them. Dependents that
+/// are cancelled are marked with `dependency_failed`, then deleted one level of dependents at a
+/// time until a level has no dependents: their batches, outcomes and events are updated and their
+/// own dependents are failed in turn. Dependents nested deeper than [`MAX_DEPENDENT_LEVELS`] stay
+/// marked and are deleted by the next transaction failing dependents. Expects `$table`,
+/// `$events`, `$finished` and `$now` to be bound.
+pub(crate) fn fail_dependents_statement() -> String {
+    format!(
+        r#"
+    FOR $level IN 0..{MAX_DEPENDENT_LEVELS} {{
+        LET $failed = IF $level = 0 THEN
+            $finished
+        ELSE
+            (DELETE type::table($table) WHERE dependency_failed=true RETURN BEFORE)
+        END;
+        IF $failed = [] {{
+            BREAK;
+        }};
+
+        LET $failed_ids = array::map($failed, |$job| record::id($job.id));
+
+        UPDATE type::table($table)
+        SET dependency_failed=true
+        WHERE
+            depends_on CONTAINSANY $failed_ids
+            AND on_dependency_failure!='continue';
+
+        UPDATE type::table($table)
+        SET depends_on=array::complement(depends_on, $failed_ids)
+        WHERE
+            depends_on CONTAINSANY $failed_ids
+            AND on_dependency_failure='continue';
+
+        IF $level > 0 {{
+            LET $finished = $failed;
+            LET $outcome = "cancelled";
+            LET $output = NONE;
+            LET $message = NONE;
+            LET $jobs = [];
+            {FINISH_BATCHES_STATEMENT}
+            {RECORD_OUTCOMES_STATEMENT}
+            {RECORD_EVENTS_STATEMENT}
+            {PUBLISH_JOBS_STATEMENT}
+        }};
+    }};
+"#
+    )
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::{poll, setup};
+use mq::{Batch, BatchProgress, Job, JobProcessor, Producer};
+use serde_json::json;
+
+#[tokio::test]
+async fn publishes_the_success_callback_when_all_jobs_succeed() {
+    let (producer, job_processor) = setup().await;
+    let job = Job::new("job", json!({}));
+    let callback = Job::new("callback", json!({}));
+    let batch = Batch::new()
+        .with_job(job.clone())
+        .with_on_success(callback.clone())
+        .with_on_failure(Job::new("failure", json!({})));
+    let batch_id = batch.id().to_owned();
+    producer.publish_batch(batch).await.unwrap();
+
+    assert_eq!(poll(&job_processor).await.unwrap().id(), job.id());
+    job_processor
+        .complete_job_with_success(job.queue(), job.kind(), job.id())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        producer.batch_progress(&batch_id).await.unwrap(),
+        BatchProgress {
+            total: 1,
+            succeeded: 1,
+            failed: 0,
+            cancelled: 0,
+            finished: true,
+        }
+    );
+    assert_eq!(poll(&job_processor).await.unwrap().id(), callback.id());
+    assert!(poll(&job_processor).await.is_none());
+}
+
+#[tokio::test]
+async fn publishes_the_failure_callback_when_a_job_fails() {
+    let (producer, job_processor) = setup().await;
+    let job = Job::new("job", json!({})).with_max_attempts(1);
+    let callback = Job::new("callback", json!({}));
+    let batch = Batch::new()
+        .with_job(job.clone())
+        .with_on_success(Job::new("success", json!({})))
+        .with_on_failure(callback.clone());
+    let batch_id = batch.id().to_owned();
+    producer.publish_batch(batch).await.unwrap();
+
+    poll(&job_processor).await.unwrap();
+    job_processor
+        .fail_job(
+            job.queue(),
+            job.kind(),
+            job.id(),
+            json!({ "error": "failed" }),
+        )
+        .await
+        .unwrap();
+
+    let progress = producer.batch_progress(&batch_id).await.unwrap();
+    assert_eq!(progress.failed, 1);
+    assert!(progress.finished);
+    assert_eq!(poll(&job_processor).await.unwrap().id(), callback.id());
+    assert!(poll(&job_processor).await.is_none());
+}
+
+#[tokio::test]
+async fn does_not_count_a_cancelled_dead_job_twice() {
+    let (producer, job_processor) = setup().await;
+    let job = Job::new("job", json!({}))
+        .with_max_attempts(1)
+        .with_priority(u8::MAX);
+    let batch = Batch::new()
+        .with_job(job.clone())
+        .with_job(Job::new("job", json!({})).with_priority(0));
+    let batch_id = batch.id().to_owned();
+    producer.publish_batch(batch).await.unwrap();
+
+    assert_eq!(poll(&job_processor).await.unwrap().id(), job.id());
+    job_processor
+        .fail_job(
+            job.queue(),
+            job.kind(),
+            job.id(),
+            json!({ "error": "failed" }),
+        )
+        .await
+        .unwrap();
+    producer
+        .cancel_by_id(job.queue(), job.kind(), job.id())
+        .await
+        .unwrap();
+
+    let progress = producer.batch_progress(&batch_id).await.unwrap();
+    assert_eq!(progress.failed, 1);
+    assert_eq!(progress.cancelled, 0);
+    assert_eq!(progress.pending(), 1);
+    assert!(!progress.finished);
+}
+
+#[tokio::test]
+async fn finishes_an_empty_batch_right_away() {
+    let (producer, job_processor) = setup().await;
+    let callback = Job::new("callback", json!({}));
+    let batch = Batch::new().with_on_complete(callback.clone());
+    let batch_id = batch.id().to_owned();
+    producer.publish_batch(batch).await.unwrap();
+
+    let progress = producer.batch_progress(&batch_id).await.unwrap();
+    assert_eq!(progress.total, 0);
+    assert!(progress.finished);
+    assert_eq!(poll(&job_processor).await.unwrap().id(), callback.id());
+}
@@ -0,0 +1,44 @@
+// every test uses its own subset of the helpers
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use mq::{Job, JobProcessor, Producer};
+use mq_surreal::{SurrealJobProcessor, SurrealProducer};
+use surrealdb::{engine::any::Any, Surreal};
+
+pub const TABLE: &str = "jobs";
+
+/// Connects to a new in-memory database with the schema of the jobs table.
+pub async fn connect() -> Arc<Surreal<Any>> {
+    let db = Arc::new(surrealdb::engine::any::connect("mem://").await.unwrap());
+    db.use_ns("mq").use_db("mq").await.unwrap();
+
+    SurrealJobProcessor::new(db.clone(), TABLE)
+        .ensure_schema()
+        .await
+        .unwrap();
+
+    db
+}
+
+/// Producer and job processor of the jobs table of a new in-memory database.
+pub async fn setup() -> (SurrealProducer, SurrealJobProcessor) {
+    let db = connect().await;
+    (
+        SurrealProducer::new(db.clone(), TABLE),
+        SurrealJobProcessor::new(db, TABLE),
+    )
+}
+
+/// Polls the next job of the default queue.
+pub async fn poll(job_processor: &SurrealJobProcessor) -> Option<Job> {
+    job_processor.poll_next_job(&["default"]).await.unwrap()
+}
+
+pub async fn exists(producer: &SurrealProducer, job: &Job) -> bool {
+    producer
+        .exists(job.queue(), job.kind(), job.id())
+        .await
+        .unwrap()
+}
@@ -0,0 +1,15 @@
+mod common;
+
+use common::TABLE;
+use mq_surreal::{SurrealJobProcessor, SurrealProducer};
+use mq_testsuite::Backend;
+
+async fn backend() -> Backend {
+    let db = common::connect().await;
+    Backend::new(
+        SurrealProducer::new(db.clone(), TABLE),
+        SurrealJobProcessor::new(db, TABLE),
+    )
+}
+
+mq_testsuite::backend_tests!(backend);
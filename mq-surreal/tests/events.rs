@@ -0,0 +1,84 @@
+mod common;
+
+use std::time::Duration;
+
+use common::TABLE;
+use futures::StreamExt;
+use mq::{Job, JobEventFilter, JobEventType, JobProcessor, Producer};
+use mq_surreal::{SurrealJobProcessor, SurrealProducer};
+use serde_json::json;
+
+async fn setup() -> (SurrealProducer, SurrealJobProcessor) {
+    let db = common::connect().await;
+    (
+        SurrealProducer::new(db.clone(), TABLE).with_events(true),
+        SurrealJobProcessor::new(db, TABLE).with_events(true),
+    )
+}
+
+#[tokio::test]
+async fn streams_the_lifecycle_events_of_a_job() {
+    let (producer, job_processor) = setup().await;
+    let job = Job::new("job", json!({}));
+    let mut events = producer
+        .subscribe(JobEventFilter::new().with_id(job.id()))
+        .await
+        .unwrap();
+
+    producer.publish(job.clone()).await.unwrap();
+    job_processor
+        .poll_next_job(&["default"])
+        .await
+        .unwrap()
+        .unwrap();
+    job_processor
+        .complete_job_with_success(job.queue(), job.kind(), job.id())
+        .await
+        .unwrap();
+
+    let mut received = Vec::new();
+    for _ in 0..3 {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .expect("an event")
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.id, job.id());
+        received.push(event.event);
+    }
+    assert_eq!(
+        received,
+        vec![
+            JobEventType::Published,
+            JobEventType::Started { attempt: 1 },
+            JobEventType::Succeeded,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn purges_events_older_than_the_retention() {
+    let (producer, _) = setup().await;
+    let job = Job::new("job", json!({}));
+    producer.publish(job.clone()).await.unwrap();
+    producer
+        .cancel_by_id(job.queue(), job.kind(), job.id())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        producer
+            .purge_events(Duration::from_secs(3600))
+            .await
+            .unwrap(),
+        0
+    );
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(
+        producer
+            .purge_events(Duration::from_millis(1))
+            .await
+            .unwrap(),
+        2
+    );
+}
@@ -0,0 +1,328 @@
+mod common;
+
+use std::time::Duration;
+
+use common::TABLE;
+use mq::{Error, Job, JobFilter, JobProcessor, JobState, JobStore, Page, Producer};
+use mq_surreal::{SurrealJobProcessor, SurrealJobStore, SurrealProducer};
+use serde_json::json;
+use time::OffsetDateTime;
+
+async fn setup() -> (SurrealProducer, SurrealJobProcessor, SurrealJobStore) {
+    let db = common::connect().await;
+    (
+        SurrealProducer::new(db.clone(), TABLE),
+        SurrealJobProcessor::new(db.clone(), TABLE),
+        SurrealJobStore::new(db, TABLE),
+    )
+}
+
+async fn list_ids(job_store: &SurrealJobStore, filter: JobFilter) -> Vec<String> {
+    let page = job_store.list_jobs(&filter, &Page::new()).await.unwrap();
+    assert!(page.next_cursor.is_none());
+    page.jobs.iter().map(|job| job.id().to_owned()).collect()
+}
+
+#[tokio::test]
+async fn gets_jobs_by_id() {
+    let (producer, _, job_store) = setup().await;
+    let job = Job::new("job", json!({ "to": "hi@example.com" }));
+    producer.publish(job.clone()).await.unwrap();
+
+    let found = job_store.get_job(job.id()).await.unwrap().unwrap();
+    assert_eq!(found.id(), job.id());
+    assert_eq!(found.payload(), job.payload());
+    assert!(job_store.get_job("missing").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn lists_jobs_in_pages() {
+    let (producer, _, job_store) = setup().await;
+    let mut ids = Vec::new();
+    for _ in 0..5 {
+        let job = Job::new("job", json!({}));
+        ids.push(job.id().to_owned());
+        producer.publish(job).await.unwrap();
+    }
+
+    let mut listed = Vec::new();
+    let mut page = Page::new().with_limit(2);
+    let mut pages = 0;
+    loop {
+        let result = job_store.list_jobs(&JobFilter::new(), &page).await.unwrap();
+        listed.extend(result.jobs.iter().map(|job| job.id().to_owned()));
+        pages += 1;
+        match result.next_cursor {
+            Some(cursor) => page = page.with_cursor(cursor),
+            None => break,
+        }
+    }
+
+    assert_eq!(pages, 3);
+    ids.sort();
+    assert_eq!(listed, ids);
+}
+
+#[tokio::test]
+async fn filters_jobs() {
+    let (producer, job_processor, job_store) = setup().await;
+    let failed = Job::new("email", json!({}));
+    let running = Job::new("email", json!({}));
+    let scheduled = Job::new("email", json!({}))
+        .with_unique_key(Some("key".into()))
+        .with_schedule_in(Duration::from_secs(3600));
+    let ready = Job::new("report", json!({}))
+        .with_queue("reports")
+        .with_header("tenant", "acme")
+        .with_header("attempt", 2);
+
+    for job in [&failed, &running, &scheduled, &ready] {
+        producer.publish(job.clone()).await.unwrap();
+    }
+    for _ in 0..2 {
+        job_processor.poll_next_job(&["default"]).await.unwrap();
+    }
+    job_processor
+        .fail_job(
+            failed.queue(),
+            failed.kind(),
+            failed.id(),
+            json!({ "error": "failed" }),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        list_ids(&job_store, JobFilter::new().with_queue("reports")).await,
+        vec![ready.id()]
+    );
+    assert_eq!(
+        list_ids(&job_store, JobFilter::new().with_kind("report")).await,
+        vec![ready.id()]
+    );
+    assert_eq!(
+        list_ids(&job_store, JobFilter::new().with_unique_key("key")).await,
+        vec![scheduled.id()]
+    );
+    assert_eq!(
+        list_ids(&job_store, JobFilter::new().with_has_error(true)).await,
+        vec![failed.id()]
+    );
+    assert_eq!(
+        list_ids(&job_store, JobFilter::new().with_state(JobState::Running)).await,
+        vec![running.id()]
+    );
+    assert_eq!(
+        list_ids(&job_store, JobFilter::new().with_state(JobState::Scheduled)).await,
+        vec![scheduled.id()]
+    );
+    assert_eq!(
+        list_ids(
+            &job_store,
+            JobFilter::new()
+                .with_scheduled_after(OffsetDateTime::now_utc() + Duration::from_secs(60))
+        )
+        .await,
+        vec![scheduled.id()]
+    );
+
+    assert_eq!(
+        list_ids(&job_store, JobFilter::new().with_header("tenant", "acme")).await,
+        vec![ready.id()]
+    );
+    let headers = JobFilter::new()
+        .with_header("tenant", "acme")
+        .with_header("attempt", 2);
+    assert_eq!(
+        list_ids(&job_store, headers.clone()).await,
+        vec![ready.id()]
+    );
+    assert_eq!(job_store.count(&headers).await.unwrap(), 1);
+    assert!(list_ids(
+        &job_store,
+        JobFilter::new()
+            .with_header("tenant", "acme")
+            .with_header("attempt", 3)
+    )
+    .await
+    .is_empty());
+
+    let retried = JobFilter::new()
+        .with_state(JobState::Ready)
+        .with_min_attempts(1)
+        .with_max_attempts(1);
+    assert_eq!(
+        list_ids(&job_store, retried.clone()).await,
+        vec![failed.id()]
+    );
+
+    assert_eq!(job_store.count(&JobFilter::new()).await.unwrap(), 4);
+    assert_eq!(job_store.count(&retried).await.unwrap(), 1);
+    assert_eq!(
+        job_store
+            .count(&JobFilter::new().with_state(JobState::Dead))
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+async fn poll_id(job_processor: &SurrealJobProcessor) -> Option<String> {
+    job_processor
+        .poll_next_job(&["default"])
+        .await
+        .unwrap()
+        .map(|job| job.id().to_owned())
+}
+
+#[tokio::test]
+async fn retries_dead_jobs() {
+    let (producer, job_processor, job_store) = setup().await;
+    let job = Job::new("job", json!({})).with_max_attempts(1);
+    producer.publish(job.clone()).await.unwrap();
+    poll_id(&job_processor).await.unwrap();
+    job_processor
+        .fail_job(
+            job.queue(),
+            job.kind(),
+            job.id(),
+            json!({ "error": "failed" }),
+        )
+        .await
+        .unwrap();
+
+    let retried = job_store.retry_now(job.id(), false).await.unwrap().unwrap();
+    assert_eq!(retried.attempts(), 1);
+    assert!(poll_id(&job_processor).await.is_none());
+
+    let retried = job_store.retry_now(job.id(), true).await.unwrap().unwrap();
+    assert_eq!(retried.attempts(), 0);
+    assert_eq!(poll_id(&job_processor).await.as_deref(), Some(job.id()));
+
+    assert!(job_store
+        .retry_now("missing", true)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn raises_max_attempts_of_dead_jobs() {
+    let (producer, job_processor, job_store) = setup().await;
+    let job = Job::new("job", json!({})).with_max_attempts(1);
+    producer.publish(job.clone()).await.unwrap();
+    poll_id(&job_processor).await.unwrap();
+    job_processor
+        .fail_job(
+            job.queue(),
+            job.kind(),
+            job.id(),
+            json!({ "error": "failed" }),
+        )
+        .await
+        .unwrap();
+
+    job_store
+        .set_max_attempts(job.id(), 2)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(poll_id(&job_processor).await.as_deref(), Some(job.id()));
+}
+
+#[tokio::test]
+async fn reschedules_jobs() {
+    let (producer, job_processor, job_store) = setup().await;
+    let job = Job::new("job", json!({})).with_schedule_in(Duration::from_secs(3600));
+    producer.publish(job.clone()).await.unwrap();
+    assert!(poll_id(&job_processor).await.is_none());
+
+    job_store
+        .reschedule(job.id(), OffsetDateTime::now_utc() - Duration::from_secs(1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(poll_id(&job_processor).await.as_deref(), Some(job.id()));
+}
+
+#[tokio::test]
+async fn reprioritises_jobs() {
+    let (producer, job_processor, job_store) = setup().await;
+    let first = Job::new("job", json!({}));
+    let second = Job::new("job", json!({}));
+    producer.publish(first.clone()).await.unwrap();
+    producer.publish(second.clone()).await.unwrap();
+
+    let job = job_store
+        .set_priority(second.id(), 10)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(job.priority(), 10);
+    assert_eq!(poll_id(&job_processor).await.as_deref(), Some(second.id()));
+    assert_eq!(poll_id(&job_processor).await.as_deref(), Some(first.id()));
+}
+
+#[tokio::test]
+async fn patches_payloads() {
+    let (producer, _, job_store) = setup().await;
+    let job = Job::new("job", json!({ "a": 1, "b": { "c": 2, "d": 3 } }));
+    producer.publish(job.clone()).await.unwrap();
+
+    let patched = job_store
+        .patch_payload(job.id(), json!({ "b": { "c": null, "e": 4 }, "f": 5 }))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        patched.payload(),
+        &json!({ "a": 1, "b": { "d": 3, "e": 4 }, "f": 5 })
+    );
+
+    // values other than objects replace the patched value as a whole
+    let patched = job_store
+        .patch_payload(job.id(), json!({ "b": [1, 2] }))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(patched.payload(), &json!({ "a": 1, "b": [1, 2], "f": 5 }));
+    for patch in [json!("payload"), json!([1, 2])] {
+        assert!(matches!(
+            job_store.patch_payload(job.id(), patch).await,
+            Err(Error::NotSupported(_))
+        ));
+    }
+    assert_eq!(
+        job_store
+            .get_job(job.id())
+            .await
+            .unwrap()
+            .unwrap()
+            .payload(),
+        &json!({ "a": 1, "b": [1, 2], "f": 5 })
+    );
+
+    assert!(job_store
+        .patch_payload("missing", json!({}))
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn releases_locks() {
+    let (producer, job_processor, job_store) = setup().await;
+    let job = Job::new("job", json!({})).with_lease_time(Duration::from_secs(3600));
+    producer.publish(job.clone()).await.unwrap();
+    poll_id(&job_processor).await.unwrap();
+    assert!(poll_id(&job_processor).await.is_none());
+
+    job_store.release_lock(job.id()).await.unwrap().unwrap();
+    let polled = job_processor
+        .poll_next_job(&["default"])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(polled.id(), job.id());
+    assert_eq!(polled.attempts(), 2);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use common::TABLE;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use mq::{Consumer, Context, JobResult, MetricsServer, Worker, WorkerHealth, JOBS_SUCCEEDED};
+use mq_surreal::SurrealJobProcessor;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_util::sync::CancellationToken;
+
+/// Serves the metrics of a recorder holding one counter on a port picked by the OS.
+async fn serve(workers: Vec<WorkerHealth>, cancellation_token: CancellationToken) -> SocketAddr {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    metrics::with_local_recorder(&recorder, || {
+        metrics::counter!(JOBS_SUCCEEDED, "kind" => "email").increment(3);
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = workers.into_iter().fold(
+        MetricsServer::new(addr, handle).with_cancellation_token(cancellation_token),
+        MetricsServer::with_worker,
+    );
+    tokio::spawn(server.serve(listener));
+    addr
+}
+
+/// Returns the status code and body of a GET request.
+async fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let status = response.split(' ').nth(1).unwrap().parse().unwrap();
+    let body = response.split_once("\r\n\r\n").unwrap().1.to_owned();
+    (status, body)
+}
+
+#[tokio::test]
+async fn serves_the_metrics() {
+    let cancellation_token = CancellationToken::new();
+    let addr = serve(Vec::new(), cancellation_token.clone()).await;
+
+    let (status, body) = get(addr, "/metrics").await;
+    assert_eq!(status, 200);
+    assert!(
+        body.contains(&format!("{JOBS_SUCCEEDED}{{kind=\"email\"}} 3")),
+        "{body}"
+    );
+    assert_eq!(get(addr, "/healthz").await.0, 200);
+    assert_eq!(get(addr, "/missing").await.0, 404);
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn is_not_ready_without_workers() {
+    let cancellation_token = CancellationToken::new();
+    let addr = serve(Vec::new(), cancellation_token.clone()).await;
+
+    assert_eq!(get(addr, "/readyz").await.0, 503);
+
+    cancellation_token.cancel();
+}
+
+#[tokio::test]
+async fn is_ready_once_its_workers_polled() {
+    let db = common::connect().await;
+    let cancellation_token = CancellationToken::new();
+    let worker_cancellation_token = CancellationToken::new();
+    let worker = Worker::new(Consumer::new().register(("job", |_: Context| async move {
+        Ok(JobResult::CompleteWithSuccess)
+    })))
+    .with_poll_interval(Some(10))
+    .with_cancellation_token(worker_cancellation_token.clone());
+    let addr = serve(vec![worker.health().clone()], cancellation_token.clone()).await;
+
+    assert_eq!(get(addr, "/readyz").await.0, 503);
+
+    let running = tokio::spawn(worker.run(SurrealJobProcessor::new(db, TABLE)));
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while get(addr, "/readyz").await.0 != 200 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the worker to become ready");
+
+    worker_cancellation_token.cancel();
+    running.await.unwrap().unwrap();
+    assert_eq!(get(addr, "/readyz").await.0, 503);
+
+    cancellation_token.cancel();
+}
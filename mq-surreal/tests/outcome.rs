@@ -0,0 +1,95 @@
+mod common;
+
+use std::time::Duration;
+
+use common::setup;
+use mq::{Job, JobOutcome, JobProcessor, Producer};
+use mq_surreal::SurrealProducer;
+use serde_json::json;
+
+async fn outcome(producer: &SurrealProducer, job: &Job) -> Option<JobOutcome> {
+    producer
+        .job_outcome(job.queue(), job.kind(), job.id())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn records_the_outcome_of_tracked_jobs() {
+    let (producer, job_processor) = setup().await;
+    let succeeded = Job::new("job", json!({})).with_track_outcome(true);
+    let failed = Job::new("job", json!({}))
+        .with_track_outcome(true)
+        .with_max_attempts(1);
+    let untracked = Job::new("job", json!({}));
+    for job in [&succeeded, &failed, &untracked] {
+        producer.publish(job.clone()).await.unwrap();
+    }
+    assert_eq!(outcome(&producer, &succeeded).await, None);
+
+    for _ in 0..3 {
+        let job = job_processor
+            .poll_next_job(&["default"])
+            .await
+            .unwrap()
+            .unwrap();
+        if job.id() == failed.id() {
+            job_processor
+                .fail_job(
+                    job.queue(),
+                    job.kind(),
+                    job.id(),
+                    json!({ "error": "failed" }),
+                )
+                .await
+                .unwrap();
+        } else {
+            job_processor
+                .complete_job_with_output(job.queue(), job.kind(), job.id(), json!(1))
+                .await
+                .unwrap();
+        }
+    }
+
+    assert_eq!(
+        outcome(&producer, &succeeded).await,
+        Some(JobOutcome::Succeeded(Some(json!(1))))
+    );
+    assert_eq!(
+        outcome(&producer, &failed).await,
+        Some(JobOutcome::Failed(Some(json!({ "error": "failed" }))))
+    );
+    assert_eq!(outcome(&producer, &untracked).await, None);
+}
+
+#[tokio::test]
+async fn purges_outcomes_older_than_the_retention() {
+    let (producer, _) = setup().await;
+    let job = Job::new("job", json!({})).with_track_outcome(true);
+    producer.publish(job.clone()).await.unwrap();
+    producer
+        .cancel_by_id(job.queue(), job.kind(), job.id())
+        .await
+        .unwrap();
+    assert_eq!(
+        outcome(&producer, &job).await,
+        Some(JobOutcome::Cancelled(None))
+    );
+
+    assert_eq!(
+        producer
+            .purge_outcomes(Duration::from_secs(3600))
+            .await
+            .unwrap(),
+        0
+    );
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(
+        producer
+            .purge_outcomes(Duration::from_millis(1))
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(outcome(&producer, &job).await, None);
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TABLE;
+use mq::{Error, Job, Producer, PublishOutcome, UniqueKeyConflict};
+use mq_surreal::SurrealProducer;
+use serde_json::json;
+use surrealdb::{engine::any::Any, Surreal};
+
+async fn setup() -> (Arc<Surreal<Any>>, SurrealProducer) {
+    let db = common::connect().await;
+    let producer = SurrealProducer::new(db.clone(), TABLE);
+    (db, producer)
+}
+
+async fn order_exists(db: &Surreal<Any>) -> bool {
+    let mut result = db.query("RETURN record::exists(order:1);").await.unwrap();
+    result.take::<Option<bool>>(0).unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn publishes_jobs_with_the_transaction_of_the_caller() {
+    let (db, producer) = setup().await;
+    let first = Job::new("job", json!({}));
+    let second = Job::new("job", json!({}));
+
+    let query = db.query("BEGIN TRANSACTION;").bind(("table", "order"));
+    let query = producer
+        .publish_with(query, vec![first.clone()])
+        .query("CREATE order:1 SET table=$table;");
+    let mut result = producer
+        .publish_with(query, vec![second.clone()])
+        .query("COMMIT TRANSACTION;")
+        .await
+        .unwrap();
+
+    for index in [1, 3] {
+        let outcomes = mq_surreal::take_publish_outcomes(&mut result, index).unwrap();
+        assert_eq!(outcomes, vec![PublishOutcome::Published]);
+    }
+    // The parameters of the caller are not overwritten by the statements publishing the jobs.
+    let table: Option<String> = result.take((2, "table")).unwrap();
+    assert_eq!(table.as_deref(), Some("order"));
+    assert!(order_exists(&db).await);
+    for job in [&first, &second] {
+        assert!(producer
+            .exists(job.queue(), job.kind(), job.id())
+            .await
+            .unwrap());
+    }
+}
+
+#[tokio::test]
+async fn does_not_publish_jobs_when_the_transaction_fails() {
+    let (db, producer) = setup().await;
+    let job = Job::new("job", json!({}));
+
+    let query = db.query("BEGIN TRANSACTION;").query("CREATE order:1;");
+    let result = producer
+        .publish_with(query, vec![job.clone()])
+        .query("THROW 'failed';")
+        .query("COMMIT TRANSACTION;")
+        .await
+        .unwrap()
+        .check();
+
+    assert!(result.is_err());
+    assert!(!order_exists(&db).await);
+    assert!(!producer
+        .exists(job.queue(), job.kind(), job.id())
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn reports_duplicate_jobs() {
+    let (db, producer) = setup().await;
+    let existing = Job::new("job", json!({})).with_unique_key(Some("key".into()));
+    producer.publish(existing.clone()).await.unwrap();
+
+    let query = db.query("BEGIN TRANSACTION;").query("CREATE order:1;");
+    let mut result = producer
+        .publish_with(
+            query,
+            vec![Job::new("job", json!({}))
+                .with_unique_key(Some("key".into()))
+                .with_on_unique_key_conflict(UniqueKeyConflict::Error)],
+        )
+        .query("COMMIT TRANSACTION;")
+        .await
+        .unwrap();
+
+    match mq_surreal::take_publish_outcomes(&mut result, 2) {
+        Err(Error::DuplicateJob(id)) => assert_eq!(id, existing.id()),
+        result => panic!("expected a duplicate job error, got {:?}", result),
+    }
+    assert!(!order_exists(&db).await);
+}
+
+#[tokio::test]
+async fn publishes_jobs_with_a_client_transaction() {
+    let (db, producer) = setup().await;
+    let job = Job::new("job", json!({}));
+
+    let tx = (*db).clone().begin().await.unwrap();
+    let mut result = producer
+        .publish_with(tx.query("CREATE order:1;"), vec![job.clone()])
+        .await
+        .unwrap();
+    let outcomes = mq_surreal::take_publish_outcomes(&mut result, 1).unwrap();
+    assert_eq!(outcomes, vec![PublishOutcome::Published]);
+    tx.cancel().await.unwrap();
+
+    assert!(!order_exists(&db).await);
+    assert!(!producer
+        .exists(job.queue(), job.kind(), job.id())
+        .await
+        .unwrap());
+}
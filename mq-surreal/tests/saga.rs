@@ -0,0 +1,223 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+
+use common::TABLE;
+use mq::{
+    Consumer, Context, Error, Job, JobProcessor, JobResult, JobStore, Producer, Saga, Worker,
+};
+use mq_surreal::{SurrealJobProcessor, SurrealJobStore, SurrealProducer};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+async fn setup() -> (Arc<SurrealProducer>, SurrealJobProcessor, SurrealJobStore) {
+    let db = common::connect().await;
+    (
+        Arc::new(SurrealProducer::new(db.clone(), TABLE)),
+        SurrealJobProcessor::new(db.clone(), TABLE),
+        SurrealJobStore::new(db, TABLE),
+    )
+}
+
+/// Fails the first `fail_job` call, as if the database was unreachable, and delegates every
+/// other call.
+struct FailJobOnce {
+    inner: SurrealJobProcessor,
+    failed: AtomicBool,
+}
+
+#[async_trait]
+impl JobProcessor for FailJobOnce {
+    async fn poll_next_job(&self, queues: &[&str]) -> Result<Option<Job>, Error> {
+        self.inner.poll_next_job(queues).await
+    }
+
+    async fn complete_job_with_success(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+    ) -> Result<(), Error> {
+        self.inner.complete_job_with_success(queue, kind, id).await
+    }
+
+    async fn complete_job_with_cancelled(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        message: Option<String>,
+    ) -> Result<(), Error> {
+        self.inner
+            .complete_job_with_cancelled(queue, kind, id, message)
+            .await
+    }
+
+    async fn fail_job(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        reason: Value,
+    ) -> Result<(), Error> {
+        if !self.failed.swap(true, Ordering::SeqCst) {
+            return Err(Error::UnknownError("database unreachable".into()));
+        }
+        self.inner.fail_job(queue, kind, id, reason).await
+    }
+
+    async fn fail_expired_jobs(&self, queues: &[&str]) -> Result<Vec<Job>, Error> {
+        self.inner.fail_expired_jobs(queues).await
+    }
+}
+
+/// Worker processing the saga steps, sending the payloads of the processed compensations.
+fn saga_worker(producer: Arc<SurrealProducer>, sender: mpsc::UnboundedSender<Value>) -> Worker {
+    Worker::new(
+        Consumer::new()
+            .register(("reserve", |_: Context| async move {
+                Ok(JobResult::CompleteWithSuccess)
+            }))
+            .register(("charge", |_: Context| async move {
+                Err(Error::UnknownError("card declined".into()))
+            }))
+            .register(("release", move |ctx: Context| {
+                let sender = sender.clone();
+                async move {
+                    sender.send(ctx.payload().clone()).unwrap();
+                    Ok(JobResult::CompleteWithSuccess)
+                }
+            }))
+            .with_compensation("default", "reserve", "release"),
+    )
+    .with_poll_interval(Some(50))
+    .with_producer(producer)
+}
+
+/// Runs a worker processing the saga steps until a compensation is processed, then for a while
+/// longer to catch repeated compensations, and returns the payloads of the processed
+/// compensations.
+async fn run_worker(
+    producer: Arc<SurrealProducer>,
+    job_processor: impl JobProcessor,
+) -> Vec<Value> {
+    let cancellation_token = CancellationToken::new();
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let worker = saga_worker(producer, sender).with_cancellation_token(cancellation_token.clone());
+
+    let mut compensations = Vec::new();
+    let collect = async {
+        compensations.push(receiver.recv().await.unwrap());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        cancellation_token.cancel();
+    };
+    let (result, _) = tokio::time::timeout(
+        Duration::from_secs(10),
+        futures::future::join(worker.run(job_processor), collect),
+    )
+    .await
+    .expect("the saga to be compensated");
+    result.unwrap();
+
+    while let Ok(payload) = receiver.try_recv() {
+        compensations.push(payload);
+    }
+    compensations
+}
+
+#[tokio::test]
+async fn compensates_the_completed_steps_of_a_failed_saga() {
+    let (producer, job_processor, _) = setup().await;
+    let saga = Saga::new()
+        .with_step(Job::new("reserve", json!({ "item": 1 })))
+        .with_step(Job::new("charge", json!({ "amount": 10 })).with_max_attempts(1));
+    producer
+        .publish_workflow(saga.into_workflow())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        run_worker(producer, job_processor).await,
+        vec![json!({ "item": 1 })]
+    );
+}
+
+#[tokio::test]
+async fn compensates_a_saga_whose_last_lease_expired() {
+    let (producer, job_processor, job_store) = setup().await;
+    let reserve = Job::new("reserve", json!({ "item": 1 }));
+    let charge = Job::new("charge", json!({ "amount": 10 }))
+        .with_max_attempts(1)
+        .with_lease_time(Duration::from_secs(1));
+    let saga = Saga::new()
+        .with_step(reserve.clone())
+        .with_step(charge.clone());
+    producer
+        .publish_workflow(saga.into_workflow())
+        .await
+        .unwrap();
+
+    // A worker completes the first step, then dies during the last attempt of the second one.
+    job_processor.poll_next_job(&["default"]).await.unwrap();
+    job_processor
+        .complete_job_with_success(reserve.queue(), reserve.kind(), reserve.id())
+        .await
+        .unwrap();
+    let polled = job_processor.poll_next_job(&["default"]).await.unwrap();
+    assert_eq!(polled.unwrap().id(), charge.id());
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    assert_eq!(
+        run_worker(producer.clone(), job_processor).await,
+        vec![json!({ "item": 1 })]
+    );
+    let failed = job_store.get_job(charge.id()).await.unwrap().unwrap();
+    assert_eq!(
+        failed.error_reason(),
+        &Some(json!({ "error": "lease expired" }))
+    );
+}
+
+#[tokio::test]
+async fn compensates_a_saga_once_when_failing_its_last_attempt_errors() {
+    let db = common::connect().await;
+    let producer = Arc::new(SurrealProducer::new(db.clone(), TABLE));
+    let saga = Saga::new()
+        .with_step(Job::new("reserve", json!({ "item": 1 })))
+        .with_step(
+            Job::new("charge", json!({ "amount": 10 }))
+                .with_max_attempts(1)
+                .with_lease_time(Duration::from_secs(1)),
+        );
+    producer
+        .publish_workflow(saga.into_workflow())
+        .await
+        .unwrap();
+
+    // The worker stops as it cannot fail the last attempt, so the saga is not compensated yet.
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let result = saga_worker(producer.clone(), sender)
+        .run(FailJobOnce {
+            inner: SurrealJobProcessor::new(db.clone(), TABLE),
+            failed: AtomicBool::new(false),
+        })
+        .await;
+    assert!(result.is_err());
+    assert!(receiver.try_recv().is_err());
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    // Once the lease expired, the next worker compensates it exactly once.
+    assert_eq!(
+        run_worker(producer, SurrealJobProcessor::new(db, TABLE)).await,
+        vec![json!({ "item": 1 })]
+    );
+}
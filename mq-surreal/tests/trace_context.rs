@@ -0,0 +1,71 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::TABLE;
+use mq::{Consumer, Context, Job, JobResult, Producer, Worker, TRACEPARENT_HEADER};
+use mq_surreal::{SurrealJobProcessor, SurrealProducer};
+use opentelemetry::trace::{TraceContextExt, TracerProvider};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{InMemorySpanExporter, SdkTracerProvider},
+};
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+#[tokio::test]
+async fn continues_the_trace_of_the_publisher_in_the_handler() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("mq")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let db = common::connect().await;
+    let producer = SurrealProducer::new(db.clone(), TABLE);
+    let publish_span = tracing::info_span!("publish");
+    let publish_context = publish_span.context().span().span_context().clone();
+    producer
+        .publish(Job::new("job", json!({})))
+        .instrument(publish_span)
+        .await
+        .unwrap();
+
+    let traceparent = Arc::new(Mutex::new(None));
+    let header = traceparent.clone();
+    let cancellation_token = CancellationToken::new();
+    let token = cancellation_token.clone();
+    let worker = Worker::new(Consumer::new().register(("job", move |ctx: Context| {
+        *header.lock().unwrap() = ctx.header(TRACEPARENT_HEADER).cloned();
+        token.cancel();
+        async move { Ok(JobResult::CompleteWithSuccess) }
+    })))
+    .with_poll_interval(Some(10))
+    .with_cancellation_token(cancellation_token);
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        worker.run(SurrealJobProcessor::new(db, TABLE)),
+    )
+    .await
+    .expect("the job to be processed")
+    .unwrap();
+
+    assert!(traceparent.lock().unwrap().is_some());
+    provider.force_flush().unwrap();
+    let spans = exporter.get_finished_spans().unwrap();
+    let job_span = spans
+        .iter()
+        .find(|span| span.name == "job")
+        .expect("the job span to be exported");
+    assert_eq!(job_span.span_context.trace_id(), publish_context.trace_id());
+    assert_eq!(job_span.parent_span_id, publish_context.span_id());
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{setup, TABLE};
+use mq::{Job, JobProcessor, Producer};
+use mq_surreal::SurrealJobProcessor;
+use serde_json::json;
+
+async fn poll_all(job_processor: &SurrealJobProcessor) -> usize {
+    let mut polled = 0;
+    while job_processor
+        .poll_next_job(&["default"])
+        .await
+        .unwrap()
+        .is_some()
+    {
+        polled += 1;
+    }
+    polled
+}
+
+#[tokio::test]
+async fn skips_duplicates_in_custom_table() {
+    let (producer, job_processor) = setup().await;
+
+    for _ in 0..2 {
+        producer
+            .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(poll_all(&job_processor).await, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn skips_duplicates_published_concurrently() {
+    let (producer, job_processor) = setup().await;
+    let producer = Arc::new(producer);
+
+    let publishes = (0..10)
+        .map(|_| {
+            let producer = producer.clone();
+            tokio::spawn(async move {
+                producer
+                    .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+    for publish in publishes {
+        publish.await.unwrap().unwrap();
+    }
+
+    assert_eq!(poll_all(&job_processor).await, 1);
+}
+
+#[tokio::test]
+async fn migration_fails_on_active_duplicates() {
+    let db = common::connect().await;
+
+    // roll the table back to before the unique index, when duplicates could be published
+    db.query(
+        r#"
+        REMOVE INDEX `jobs_active_unique_key` ON jobs;
+        UPSERT mq_migrations:jobs SET version=2;
+        FOR $id IN ["first", "second"] {
+            CREATE type::record("jobs", $id)
+            SET created_at=time::now(),
+                updated_at=time::now(),
+                scheduled_at=time::now(),
+                queue="default",
+                kind="job",
+                payload={},
+                attempts=0,
+                max_attempts=3,
+                priority=0,
+                unique_key="key",
+                lease_time=60;
+        };
+        "#,
+    )
+    .await
+    .unwrap()
+    .check()
+    .unwrap();
+
+    let err = SurrealJobProcessor::new(db, TABLE)
+        .ensure_schema()
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("default job key [first, second]"),
+        "{err}"
+    );
+}
@@ -0,0 +1,364 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use common::{exists, TABLE};
+use futures::{FutureExt, StreamExt};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use mq::{
+    Consumer, Context, Error, Job, JobEventFilter, JobEventStream, JobEventType, JobResult,
+    Producer, Worker, WorkerHooks, JOBS_FAILED, JOBS_SUCCEEDED, QUEUE_DEPTH,
+};
+use mq_surreal::{SurrealJobProcessor, SurrealProducer};
+use serde_json::json;
+use surrealdb::{engine::any::Any, Surreal};
+use tokio_util::sync::CancellationToken;
+
+/// Records the hooks called for each job kind.
+#[derive(Clone, Default)]
+struct RecordingHooks {
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl WorkerHooks for RecordingHooks {
+    async fn on_start(&self, job: &Job) {
+        self.record(job, "start");
+    }
+
+    async fn on_success(&self, job: &Job, _duration: Duration) {
+        self.record(job, "success");
+    }
+
+    async fn on_failure(&self, job: &Job, _duration: Duration, _error: &Error) {
+        self.record(job, "failure");
+    }
+
+    async fn on_exhausted(&self, job: &Job, _duration: Duration, _error: &Error) {
+        self.record(job, "exhausted");
+    }
+}
+
+impl RecordingHooks {
+    fn record(&self, job: &Job, hook: &str) {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", job.kind(), hook));
+    }
+}
+
+/// Publishes a succeeding and a failing job, and runs a worker until it processed both.
+async fn run_worker(hooks: RecordingHooks, queue_depth_interval: Option<u64>) {
+    let db = common::connect().await;
+    let producer = Arc::new(SurrealProducer::new(db.clone(), TABLE));
+    producer
+        .publish(Job::new("succeed", json!({})).with_priority(1))
+        .await
+        .unwrap();
+    producer
+        .publish(Job::new("fail", json!({})).with_max_attempts(1))
+        .await
+        .unwrap();
+
+    let cancellation_token = CancellationToken::new();
+    let token = cancellation_token.clone();
+    let worker = Worker::new(
+        Consumer::new()
+            .register(("succeed", |_: Context| async move {
+                Ok(JobResult::CompleteWithSuccess)
+            }))
+            .register(("fail", move |_: Context| {
+                let token = token.clone();
+                async move {
+                    token.cancel();
+                    Err(Error::UnknownError("failed".into()))
+                }
+            })),
+    )
+    .with_concurrency(Some(1))
+    .with_poll_interval(Some(50))
+    .with_queue_depth_interval(queue_depth_interval)
+    .with_producer(producer)
+    .with_hooks(hooks)
+    .with_cancellation_token(cancellation_token);
+
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        worker.run(SurrealJobProcessor::new(db, TABLE)),
+    )
+    .await
+    .expect("the jobs to be processed")
+    .unwrap();
+}
+
+#[tokio::test]
+async fn calls_the_hooks_around_each_job() {
+    let hooks = RecordingHooks::default();
+    run_worker(hooks.clone(), None).await;
+
+    assert_eq!(
+        *hooks.calls.lock().unwrap(),
+        vec![
+            "succeed start",
+            "succeed success",
+            "fail start",
+            "fail failure",
+            "fail exhausted",
+        ]
+    );
+}
+
+#[test]
+fn records_the_metrics_of_the_jobs_and_queues() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    metrics::with_local_recorder(&recorder, || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(run_worker(RecordingHooks::default(), Some(10)))
+    });
+
+    let metrics = snapshotter.snapshot().into_vec();
+    let value = |name: &str, label: (&str, &str)| {
+        metrics
+            .iter()
+            .find(|(key, _, _, _)| {
+                key.key().name() == name
+                    && key.key().labels().any(|l| (l.key(), l.value()) == label)
+            })
+            .map(|(_, _, _, value)| value)
+    };
+
+    assert_eq!(
+        value(JOBS_SUCCEEDED, ("kind", "succeed")),
+        Some(&DebugValue::Counter(1))
+    );
+    assert_eq!(
+        value(JOBS_FAILED, ("kind", "fail")),
+        Some(&DebugValue::Counter(1))
+    );
+    assert!(matches!(
+        value(QUEUE_DEPTH, ("state", "ready")),
+        Some(DebugValue::Gauge(_))
+    ));
+}
+
+/// Publishes `parent`, whose handler enqueues `children` and returns `result`, and runs a worker
+/// until it processed it. Returns the result of the worker and the results of enqueueing.
+async fn run_parent(
+    db: Arc<Surreal<Any>>,
+    producer: Option<Arc<SurrealProducer>>,
+    parent: Job,
+    children: Vec<Job>,
+    result: fn() -> Result<JobResult, Error>,
+) -> (Result<(), Error>, Vec<Result<(), Error>>) {
+    SurrealProducer::new(db.clone(), TABLE)
+        .publish(parent)
+        .await
+        .unwrap();
+
+    let enqueued = Arc::new(Mutex::new(Vec::new()));
+    let cancellation_token = CancellationToken::new();
+    let token = cancellation_token.clone();
+    let results = enqueued.clone();
+    let mut worker = Worker::new(Consumer::new().register(("parent", move |ctx: Context| {
+        let token = token.clone();
+        let children = children.clone();
+        let results = results.clone();
+        async move {
+            for child in children {
+                let result = ctx.enqueue(child);
+                results.lock().unwrap().push(result);
+            }
+            token.cancel();
+            result()
+        }
+    })))
+    .with_concurrency(Some(1))
+    .with_poll_interval(Some(50))
+    .with_cancellation_token(cancellation_token);
+    if let Some(producer) = producer {
+        worker = worker.with_producer(producer);
+    }
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(10),
+        worker.run(SurrealJobProcessor::new(db, TABLE)),
+    )
+    .await
+    .expect("the parent job to be processed");
+    let enqueued = std::mem::take(&mut *enqueued.lock().unwrap());
+    (result, enqueued)
+}
+
+fn parent() -> Job {
+    Job::new("parent", json!({})).with_max_attempts(1)
+}
+
+fn child() -> Job {
+    Job::new("child", json!({})).with_queue("children")
+}
+
+#[tokio::test]
+async fn publishes_enqueued_jobs_on_success() {
+    let db = common::connect().await;
+    let producer = Arc::new(SurrealProducer::new(db.clone(), TABLE));
+    let child = child();
+
+    let (result, enqueued) = run_parent(
+        db,
+        Some(producer.clone()),
+        parent(),
+        vec![child.clone()],
+        || Ok(JobResult::CompleteWithSuccess),
+    )
+    .await;
+
+    result.unwrap();
+    assert!(enqueued.iter().all(Result::is_ok));
+    assert!(exists(&producer, &child).await);
+}
+
+#[tokio::test]
+async fn discards_enqueued_jobs_on_failure() {
+    let db = common::connect().await;
+    let producer = Arc::new(SurrealProducer::new(db.clone(), TABLE));
+    let child = child();
+
+    let (result, _) = run_parent(
+        db,
+        Some(producer.clone()),
+        parent(),
+        vec![child.clone()],
+        || Err(Error::UnknownError("failed".into())),
+    )
+    .await;
+
+    result.unwrap();
+    assert!(!exists(&producer, &child).await);
+}
+
+#[tokio::test]
+async fn discards_enqueued_jobs_on_cancellation() {
+    let db = common::connect().await;
+    let producer = Arc::new(SurrealProducer::new(db.clone(), TABLE));
+    let child = child();
+
+    let (result, _) = run_parent(
+        db,
+        Some(producer.clone()),
+        parent(),
+        vec![child.clone()],
+        || Ok(JobResult::CompleteWithCancelled(None)),
+    )
+    .await;
+
+    result.unwrap();
+    assert!(!exists(&producer, &child).await);
+}
+
+#[tokio::test]
+async fn publishes_enqueued_jobs_atomically_with_the_completion() {
+    let db = common::connect().await;
+    let producer = Arc::new(SurrealProducer::new(db.clone(), TABLE));
+    let existing = Job::new("existing", json!({})).with_schedule_in(Duration::from_secs(3600));
+    producer.publish(existing.clone()).await.unwrap();
+    let child = child();
+    // publishing a job with the id of an existing one fails the whole transaction
+    let conflicting = Job::new("existing", json!({})).with_id(existing.id());
+
+    let parent = parent();
+
+    let (result, _) = run_parent(
+        db,
+        Some(producer.clone()),
+        parent.clone(),
+        vec![child.clone(), conflicting],
+        || Ok(JobResult::CompleteWithSuccess),
+    )
+    .await;
+
+    assert!(result.is_err());
+    assert!(!exists(&producer, &child).await);
+    assert!(
+        exists(&producer, &parent).await,
+        "the parent is not completed"
+    );
+}
+
+#[tokio::test]
+async fn rejects_enqueued_jobs_without_a_producer() {
+    let db = common::connect().await;
+    let producer = SurrealProducer::new(db.clone(), TABLE);
+    let child = child();
+
+    let (result, enqueued) = run_parent(db, None, parent(), vec![child.clone()], || {
+        Ok(JobResult::CompleteWithSuccess)
+    })
+    .await;
+
+    result.unwrap();
+    assert!(matches!(enqueued[..], [Err(Error::NotSupported(_))]));
+    assert!(!exists(&producer, &child).await);
+}
+
+/// Records whether the Cancelled event of the job was already emitted when on_cancelled is
+/// called.
+struct CancelledEventHooks {
+    events: Mutex<JobEventStream>,
+    emitted: Arc<Mutex<Option<bool>>>,
+}
+
+#[async_trait]
+impl WorkerHooks for CancelledEventHooks {
+    async fn on_cancelled(&self, _job: &Job, _duration: Duration, _message: Option<&str>) {
+        let mut events = self.events.lock().unwrap();
+        let mut emitted = false;
+        while let Some(Some(Ok(event))) = events.next().now_or_never() {
+            emitted |= matches!(event.event, JobEventType::Cancelled { .. });
+        }
+        *self.emitted.lock().unwrap() = Some(emitted);
+    }
+}
+
+#[tokio::test]
+async fn emits_the_cancelled_event_before_calling_the_hooks() {
+    let db = common::connect().await;
+    SurrealProducer::new(db.clone(), TABLE)
+        .publish(Job::new("cancel", json!({})))
+        .await
+        .unwrap();
+
+    let cancellation_token = CancellationToken::new();
+    let token = cancellation_token.clone();
+    let worker = Worker::new(Consumer::new().register(("cancel", move |_: Context| {
+        token.cancel();
+        async move { Ok(JobResult::CompleteWithCancelled(None)) }
+    })))
+    .with_poll_interval(Some(50))
+    .with_cancellation_token(cancellation_token);
+    let emitted = Arc::new(Mutex::new(None));
+    let events = Mutex::new(worker.subscribe(JobEventFilter::new()));
+    let worker = worker.with_hooks(CancelledEventHooks {
+        events,
+        emitted: emitted.clone(),
+    });
+
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        worker.run(SurrealJobProcessor::new(db, TABLE)),
+    )
+    .await
+    .expect("the job to be processed")
+    .unwrap();
+
+    assert_eq!(*emitted.lock().unwrap(), Some(true));
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use common::{exists, poll, setup};
+use mq::{Batch, DependencyFailure, Job, JobProcessor, Producer, Workflow, WorkflowProgress};
+use serde_json::json;
+
+#[tokio::test]
+async fn runs_dependents_after_their_dependencies() {
+    let (producer, job_processor) = setup().await;
+    let first = Job::new("job", json!({}));
+    let second = Job::new("job", json!({})).with_depends_on([first.id()]);
+    producer
+        .publish_workflow(
+            Workflow::new()
+                .with_job(first.clone())
+                .with_job(second.clone()),
+        )
+        .await
+        .unwrap();
+
+    let polled = poll(&job_processor).await.unwrap();
+    assert_eq!(polled.id(), first.id());
+    assert!(poll(&job_processor).await.is_none());
+    job_processor
+        .complete_job_with_output(first.queue(), first.kind(), first.id(), json!({ "a": 1 }))
+        .await
+        .unwrap();
+
+    let polled = poll(&job_processor).await.unwrap();
+    assert_eq!(polled.id(), second.id());
+    assert_eq!(
+        polled.dependency_outputs().get(first.id()),
+        Some(&json!({ "a": 1 }))
+    );
+}
+
+#[tokio::test]
+async fn fails_dependents_with_the_failed_job() {
+    let (producer, job_processor) = setup().await;
+    let first = Job::new("job", json!({})).with_max_attempts(1);
+    let second = Job::new("job", json!({})).with_depends_on([first.id()]);
+    let third = Job::new("job", json!({})).with_depends_on([second.id()]);
+    let fallback = Job::new("job", json!({}))
+        .with_depends_on([third.id()])
+        .with_on_dependency_failure(DependencyFailure::Continue);
+    let workflow = Workflow::new()
+        .with_job(first.clone())
+        .with_job(second.clone())
+        .with_job(third.clone())
+        .with_job(fallback.clone());
+    let workflow_id = workflow.id().to_owned();
+    producer.publish_workflow(workflow).await.unwrap();
+
+    poll(&job_processor).await.unwrap();
+    job_processor
+        .fail_job(
+            first.queue(),
+            first.kind(),
+            first.id(),
+            json!({ "error": "failed" }),
+        )
+        .await
+        .unwrap();
+
+    // The dependents are cancelled transitively by the transaction failing the job.
+    assert!(!exists(&producer, &second).await);
+    assert!(!exists(&producer, &third).await);
+    assert_eq!(
+        producer.workflow_progress(&workflow_id).await.unwrap(),
+        WorkflowProgress {
+            waiting: 0,
+            ready: 1,
+            running: 0,
+            failed: 1,
+        }
+    );
+    assert_eq!(poll(&job_processor).await.unwrap().id(), fallback.id());
+}
+
+#[tokio::test]
+async fn counts_cancelled_dependents_in_their_batch() {
+    let (producer, _) = setup().await;
+    let first = Job::new("job", json!({}));
+    producer.publish(first.clone()).await.unwrap();
+    let batch = Batch::new().with_job(Job::new("job", json!({})).with_depends_on([first.id()]));
+    let batch_id = batch.id().to_owned();
+    producer.publish_batch(batch).await.unwrap();
+
+    producer
+        .cancel_by_id(first.queue(), first.kind(), first.id())
+        .await
+        .unwrap();
+
+    let progress = producer.batch_progress(&batch_id).await.unwrap();
+    assert_eq!(progress.cancelled, 1);
+    assert!(progress.finished);
+}
+
+#[tokio::test]
+async fn treats_completed_dependencies_as_satisfied() {
+    let (producer, job_processor) = setup().await;
+    let first = Job::new("job", json!({}));
+    producer.publish(first.clone()).await.unwrap();
+    poll(&job_processor).await.unwrap();
+    job_processor
+        .complete_job_with_success(first.queue(), first.kind(), first.id())
+        .await
+        .unwrap();
+
+    let second = Job::new("job", json!({})).with_depends_on([first.id()]);
+    producer.publish(second.clone()).await.unwrap();
+
+    let polled = poll(&job_processor).await.unwrap();
+    assert_eq!(polled.id(), second.id());
+    assert!(polled.depends_on().is_empty());
+}
+
+#[tokio::test]
+async fn keeps_dependencies_published_later_in_the_same_call() {
+    let (producer, job_processor) = setup().await;
+    let first = Job::new("job", json!({}));
+    let second = Job::new("job", json!({})).with_depends_on([first.id()]);
+    producer
+        .publish_many(vec![second.clone(), first.clone()])
+        .await
+        .unwrap();
+
+    assert_eq!(poll(&job_processor).await.unwrap().id(), first.id());
+    assert!(poll(&job_processor).await.is_none());
+}
@@ -0,0 +1,603 @@
+//! Behavioural tests pinning down the contract of [`JobProcessor`] and [`Producer`].
+//!
+//! Every test takes a fresh [`Backend`] and panics when the backend does not behave as expected.
+//! [`backend_tests!`] generates a `#[tokio::test]` for each of them from an async factory:
+//!
+//! ```ignore
+//! async fn backend() -> mq_testsuite::Backend {
+//!     let db = /* fresh database */;
+//!     mq_testsuite::Backend::new(MyProducer::new(db.clone()), MyJobProcessor::new(db))
+//! }
+//!
+//! mq_testsuite::backend_tests!(backend);
+//! ```
+
+use std::{sync::Arc, time::Duration};
+
+use mq::{Error, Job, JobProcessor, Producer, PublishOutcome, UniqueKeyConflict};
+use serde_json::json;
+
+/// Producer and job processor of the backend under test, sharing the same empty storage.
+#[derive(Clone)]
+pub struct Backend {
+    producer: Arc<dyn Producer>,
+    job_processor: Arc<dyn JobProcessor>,
+}
+
+impl Backend {
+    pub fn new<P, J>(producer: P, job_processor: J) -> Self
+    where
+        P: Producer + 'static,
+        J: JobProcessor + 'static,
+    {
+        Self {
+            producer: Arc::new(producer),
+            job_processor: Arc::new(job_processor),
+        }
+    }
+
+    pub fn producer(&self) -> &dyn Producer {
+        self.producer.as_ref()
+    }
+
+    pub fn job_processor(&self) -> &dyn JobProcessor {
+        self.job_processor.as_ref()
+    }
+
+    async fn publish(&self, job: Job) -> Job {
+        self.producer
+            .publish(job.clone())
+            .await
+            .expect("publish failed");
+        job
+    }
+
+    async fn poll(&self, queues: &[&str]) -> Option<Job> {
+        self.job_processor
+            .poll_next_job(queues)
+            .await
+            .expect("poll_next_job failed")
+    }
+
+    async fn exists(&self, job: &Job) -> bool {
+        self.producer
+            .exists(job.queue(), job.kind(), job.id())
+            .await
+            .expect("exists failed")
+    }
+
+    async fn complete(&self, job: &Job) {
+        self.job_processor
+            .complete_job_with_success(job.queue(), job.kind(), job.id())
+            .await
+            .expect("complete_job_with_success failed");
+    }
+
+    async fn fail(&self, job: &Job) {
+        self.job_processor
+            .fail_job(
+                job.queue(),
+                job.kind(),
+                job.id(),
+                json!({ "error": "failed" }),
+            )
+            .await
+            .expect("fail_job failed");
+    }
+}
+
+/// Generates a `#[tokio::test]` running each test of the suite against the backend returned by
+/// the async factory. The crate using it needs `tokio` with the `macros` and `rt-multi-thread`
+/// features. Attributes before the factory are added to every test, e.g. to ignore the tests of
+/// a backend that needs a server:
+///
+/// ```ignore
+/// mq_testsuite::backend_tests!(#[ignore = "needs a server"] backend);
+/// ```
+#[macro_export]
+macro_rules! backend_tests {
+    (@test [$(#[$attr:meta])*] $factory:path, $test:ident) => {
+        #[tokio::test(flavor = "multi_thread")]
+        $(#[$attr])*
+        async fn $test() {
+            $crate::$test($factory().await).await;
+        }
+    };
+    (@tests $attrs:tt $factory:path; $($test:ident),+ $(,)?) => {
+        $(
+            $crate::backend_tests!(@test $attrs $factory, $test);
+        )+
+    };
+    ($(#[$attr:meta])* $factory:path) => {
+        $crate::backend_tests!(
+            @tests [$(#[$attr])*] $factory;
+            polls_higher_priority_first,
+            polls_same_priority_in_publish_order,
+            skips_jobs_scheduled_in_the_future,
+            polls_only_the_requested_queues,
+            does_not_poll_leased_jobs,
+            redelivers_jobs_after_lease_expiry,
+            counts_attempts_until_exhausted,
+            rejects_unique_key_while_active,
+            releases_unique_key_after_completion,
+            reports_publish_outcomes,
+            replaces_job_on_unique_key_conflict,
+            reschedules_job_on_unique_key_conflict,
+            keeps_leased_job_on_unique_key_conflict,
+            fails_publish_on_unique_key_conflict,
+            publishes_many_jobs,
+            publish_many_is_atomic,
+            publish_and_wait_publishes_only_if_supported,
+            complete_with_success_removes_job,
+            complete_with_cancelled_removes_job,
+            cancel_by_id_removes_job,
+            cancel_by_unique_key_removes_job,
+            exists_checks_queue_and_kind,
+        );
+    };
+    ($(#[$attr:meta])* $factory:path; $($test:ident),+ $(,)?) => {
+        $crate::backend_tests!(@tests [$(#[$attr])*] $factory; $($test),+);
+    };
+}
+
+pub async fn polls_higher_priority_first(backend: Backend) {
+    let low = backend.publish(Job::new("low", json!({}))).await;
+    let high = backend
+        .publish(Job::new("high", json!({})).with_priority(10))
+        .await;
+    let medium = backend
+        .publish(Job::new("medium", json!({})).with_priority(5))
+        .await;
+
+    for expected in [&high, &medium, &low] {
+        let job = backend
+            .poll(&["default"])
+            .await
+            .expect("job was not polled");
+        assert_eq!(job.id(), expected.id());
+    }
+    assert!(backend.poll(&["default"]).await.is_none());
+}
+
+pub async fn polls_same_priority_in_publish_order(backend: Backend) {
+    let mut published = Vec::new();
+    for i in 0..3 {
+        published.push(backend.publish(Job::new("job", json!({ "i": i }))).await);
+        // backends may store timestamps with a millisecond precision
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    for expected in &published {
+        let job = backend
+            .poll(&["default"])
+            .await
+            .expect("job was not polled");
+        assert_eq!(job.id(), expected.id());
+        assert_eq!(job.payload(), expected.payload());
+    }
+}
+
+pub async fn skips_jobs_scheduled_in_the_future(backend: Backend) {
+    backend
+        .publish(
+            Job::new("later", json!({}))
+                .with_priority(10)
+                .with_schedule_in(Duration::from_secs(3600)),
+        )
+        .await;
+    let now = backend.publish(Job::new("now", json!({}))).await;
+
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+    assert_eq!(job.id(), now.id());
+    assert!(backend.poll(&["default"]).await.is_none());
+}
+
+pub async fn polls_only_the_requested_queues(backend: Backend) {
+    let other = backend
+        .publish(Job::new("job", json!({})).with_queue("other"))
+        .await;
+
+    assert!(backend.poll(&["default"]).await.is_none());
+
+    let job = backend
+        .poll(&["default", "other"])
+        .await
+        .expect("job was not polled");
+    assert_eq!(job.id(), other.id());
+    assert_eq!(job.queue(), "other");
+}
+
+pub async fn does_not_poll_leased_jobs(backend: Backend) {
+    let published = backend.publish(Job::new("job", json!({}))).await;
+
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+    assert_eq!(job.id(), published.id());
+    assert_eq!(job.attempts(), 1);
+    assert!(backend.poll(&["default"]).await.is_none());
+}
+
+pub async fn redelivers_jobs_after_lease_expiry(backend: Backend) {
+    let published = backend
+        .publish(Job::new("job", json!({})).with_lease_time(Duration::from_secs(1)))
+        .await;
+
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+    assert_eq!(job.attempts(), 1);
+
+    // backends may compare leases with a second precision
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not redelivered");
+    assert_eq!(job.id(), published.id());
+    assert_eq!(job.attempts(), 2);
+}
+
+pub async fn counts_attempts_until_exhausted(backend: Backend) {
+    let published = backend
+        .publish(Job::new("job", json!({})).with_max_attempts(2))
+        .await;
+
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+    assert_eq!(job.attempts(), 1);
+    assert!(job.error_reason().is_none());
+    backend.fail(&job).await;
+
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not retried");
+    assert_eq!(job.id(), published.id());
+    assert_eq!(job.attempts(), 2);
+    assert_eq!(job.error_reason(), &Some(json!({ "error": "failed" })));
+    backend.fail(&job).await;
+
+    assert!(backend.poll(&["default"]).await.is_none());
+}
+
+pub async fn rejects_unique_key_while_active(backend: Backend) {
+    let first = backend
+        .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+        .await;
+    backend
+        .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+        .await;
+    let other_kind = backend
+        .publish(Job::new("other", json!({})).with_unique_key(Some("key".into())))
+        .await;
+
+    let mut polled = Vec::new();
+    while let Some(job) = backend.poll(&["default"]).await {
+        polled.push(job.id().to_owned());
+    }
+    polled.sort();
+
+    let mut expected = vec![first.id().to_owned(), other_kind.id().to_owned()];
+    expected.sort();
+    assert_eq!(polled, expected);
+}
+
+pub async fn releases_unique_key_after_completion(backend: Backend) {
+    backend
+        .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+        .await;
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+    backend.complete(&job).await;
+
+    let second = backend
+        .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+        .await;
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("unique key was not released");
+    assert_eq!(job.id(), second.id());
+}
+
+pub async fn reports_publish_outcomes(backend: Backend) {
+    let first = Job::new("job", json!({})).with_unique_key(Some("key".into()));
+    let outcome = backend
+        .producer
+        .publish(first.clone())
+        .await
+        .expect("publish failed");
+    assert_eq!(outcome, PublishOutcome::Published);
+
+    let outcome = backend
+        .producer
+        .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+        .await
+        .expect("publish failed");
+    assert_eq!(outcome, PublishOutcome::Duplicate(first.id().to_owned()));
+}
+
+pub async fn replaces_job_on_unique_key_conflict(backend: Backend) {
+    let first = backend
+        .publish(
+            Job::new("job", json!({ "version": 1 }))
+                .with_unique_key(Some("key".into()))
+                .with_schedule_in(Duration::from_secs(3600)),
+        )
+        .await;
+
+    let outcome = backend
+        .producer
+        .publish(
+            Job::new("job", json!({ "version": 2 }))
+                .with_unique_key(Some("key".into()))
+                .with_on_unique_key_conflict(UniqueKeyConflict::Replace),
+        )
+        .await
+        .expect("publish failed");
+    assert_eq!(outcome, PublishOutcome::Replaced(first.id().to_owned()));
+
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("replaced job was not polled");
+    assert_eq!(job.id(), first.id());
+    assert_eq!(job.payload(), &json!({ "version": 2 }));
+    assert!(backend.poll(&["default"]).await.is_none());
+}
+
+pub async fn reschedules_job_on_unique_key_conflict(backend: Backend) {
+    let first = backend
+        .publish(
+            Job::new("job", json!({ "version": 1 }))
+                .with_unique_key(Some("key".into()))
+                .with_schedule_in(Duration::from_secs(3600)),
+        )
+        .await;
+
+    let outcome = backend
+        .producer
+        .publish(
+            Job::new("job", json!({ "version": 2 }))
+                .with_unique_key(Some("key".into()))
+                .with_on_unique_key_conflict(UniqueKeyConflict::Reschedule),
+        )
+        .await
+        .expect("publish failed");
+    assert_eq!(outcome, PublishOutcome::Replaced(first.id().to_owned()));
+
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("rescheduled job was not polled");
+    assert_eq!(job.id(), first.id());
+    assert_eq!(job.payload(), &json!({ "version": 1 }));
+}
+
+pub async fn keeps_leased_job_on_unique_key_conflict(backend: Backend) {
+    let first = backend
+        .publish(Job::new("job", json!({ "version": 1 })).with_unique_key(Some("key".into())))
+        .await;
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+
+    for on_unique_key_conflict in [UniqueKeyConflict::Replace, UniqueKeyConflict::Reschedule] {
+        let outcome = backend
+            .producer
+            .publish(
+                Job::new("job", json!({ "version": 2 }))
+                    .with_unique_key(Some("key".into()))
+                    .with_schedule_in(Duration::from_secs(3600))
+                    .with_on_unique_key_conflict(on_unique_key_conflict),
+            )
+            .await
+            .expect("publish failed");
+        assert_eq!(outcome, PublishOutcome::Duplicate(first.id().to_owned()));
+    }
+
+    // the failed attempt is retried with the original payload and schedule
+    backend.fail(&job).await;
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("failed job was not polled");
+    assert_eq!(job.id(), first.id());
+    assert_eq!(job.payload(), &json!({ "version": 1 }));
+}
+
+pub async fn fails_publish_on_unique_key_conflict(backend: Backend) {
+    let first = backend
+        .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+        .await;
+
+    let result = backend
+        .producer
+        .publish(
+            Job::new("job", json!({}))
+                .with_unique_key(Some("key".into()))
+                .with_on_unique_key_conflict(UniqueKeyConflict::Error),
+        )
+        .await;
+    match result {
+        Err(Error::DuplicateJob(id)) => assert_eq!(id, first.id()),
+        result => panic!("expected a duplicate job error, got {:?}", result),
+    }
+
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+    assert_eq!(job.id(), first.id());
+    assert!(backend.poll(&["default"]).await.is_none());
+}
+
+pub async fn publishes_many_jobs(backend: Backend) {
+    let existing = backend
+        .publish(Job::new("job", json!({})).with_unique_key(Some("existing".into())))
+        .await;
+
+    let first = Job::new("job", json!({})).with_unique_key(Some("key".into()));
+    let jobs = vec![
+        first.clone(),
+        Job::new("job", json!({})).with_unique_key(Some("existing".into())),
+        Job::new("job", json!({})).with_unique_key(Some("key".into())),
+        Job::new("job", json!({})),
+    ];
+    let outcomes = backend
+        .producer
+        .publish_many(jobs.clone())
+        .await
+        .expect("publish_many failed");
+    assert_eq!(
+        outcomes,
+        vec![
+            PublishOutcome::Published,
+            PublishOutcome::Duplicate(existing.id().to_owned()),
+            PublishOutcome::Duplicate(first.id().to_owned()),
+            PublishOutcome::Published,
+        ]
+    );
+
+    // Jobs published together may be polled in any order.
+    let mut polled = Vec::new();
+    while let Some(job) = backend.poll(&["default"]).await {
+        polled.push(job.id().to_owned());
+    }
+    polled.sort();
+    let mut expected = vec![
+        existing.id().to_owned(),
+        first.id().to_owned(),
+        jobs[3].id().to_owned(),
+    ];
+    expected.sort();
+    assert_eq!(polled, expected);
+}
+
+pub async fn publish_many_is_atomic(backend: Backend) {
+    let existing = backend
+        .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+        .await;
+
+    let published = Job::new("job", json!({}));
+    let result = backend
+        .producer
+        .publish_many(vec![
+            published.clone(),
+            Job::new("job", json!({}))
+                .with_unique_key(Some("key".into()))
+                .with_on_unique_key_conflict(UniqueKeyConflict::Error),
+        ])
+        .await;
+    match result {
+        Err(Error::DuplicateJob(id)) => assert_eq!(id, existing.id()),
+        result => panic!("expected a duplicate job error, got {:?}", result),
+    }
+
+    assert!(!backend.exists(&published).await);
+}
+
+pub async fn publish_and_wait_publishes_only_if_supported(backend: Backend) {
+    let job = Job::new("job", json!({}));
+
+    match backend
+        .producer
+        .publish_and_wait(job.clone(), Duration::ZERO)
+        .await
+    {
+        Ok(None) => assert!(backend.exists(&job).await),
+        Err(Error::NotSupported(_)) => assert!(!backend.exists(&job).await),
+        result => panic!("expected no outcome yet, got {:?}", result),
+    }
+}
+
+pub async fn complete_with_success_removes_job(backend: Backend) {
+    backend.publish(Job::new("job", json!({}))).await;
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+    backend.complete(&job).await;
+
+    assert!(!backend.exists(&job).await);
+    assert!(backend.poll(&["default"]).await.is_none());
+}
+
+pub async fn complete_with_cancelled_removes_job(backend: Backend) {
+    backend.publish(Job::new("job", json!({}))).await;
+    let job = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+    backend
+        .job_processor
+        .complete_job_with_cancelled(job.queue(), job.kind(), job.id(), Some("cancelled".into()))
+        .await
+        .expect("complete_job_with_cancelled failed");
+
+    assert!(!backend.exists(&job).await);
+    assert!(backend.poll(&["default"]).await.is_none());
+}
+
+pub async fn cancel_by_id_removes_job(backend: Backend) {
+    let job = backend.publish(Job::new("job", json!({}))).await;
+    assert!(backend.exists(&job).await);
+
+    backend
+        .producer
+        .cancel_by_id(job.queue(), job.kind(), job.id())
+        .await
+        .expect("cancel_by_id failed");
+
+    assert!(!backend.exists(&job).await);
+    assert!(backend.poll(&["default"]).await.is_none());
+}
+
+pub async fn cancel_by_unique_key_removes_job(backend: Backend) {
+    let job = backend
+        .publish(Job::new("job", json!({})).with_unique_key(Some("key".into())))
+        .await;
+    let other = backend.publish(Job::new("job", json!({}))).await;
+
+    backend
+        .producer
+        .cancel_by_unique_key(job.queue(), job.kind(), "key")
+        .await
+        .expect("cancel_by_unique_key failed");
+
+    assert!(!backend.exists(&job).await);
+    assert!(backend.exists(&other).await);
+    let polled = backend
+        .poll(&["default"])
+        .await
+        .expect("job was not polled");
+    assert_eq!(polled.id(), other.id());
+}
+
+pub async fn exists_checks_queue_and_kind(backend: Backend) {
+    let job = backend.publish(Job::new("job", json!({}))).await;
+
+    assert!(backend.exists(&job).await);
+    assert!(!backend
+        .producer
+        .exists("other", job.kind(), job.id())
+        .await
+        .expect("exists failed"));
+    assert!(!backend
+        .producer
+        .exists(job.queue(), "other", job.id())
+        .await
+        .expect("exists failed"));
+}
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Job;
+
+/// Group of jobs tracked together with callback jobs published once all of them finished.
+///
+/// A job of the batch is finished when it succeeds, is cancelled or exhausts its attempts.
+#[derive(Debug)]
+pub struct Batch {
+    id: String,
+    jobs: Vec<Job>,
+    on_complete: Option<Job>,
+    on_success: Option<Job>,
+    on_failure: Option<Job>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self {
+            id: xid::new().to_string(),
+            jobs: Vec::new(),
+            on_complete: None,
+            on_success: None,
+            on_failure: None,
+        }
+    }
+
+    pub fn with_id<S: Into<String>>(mut self, id: S) -> Self {
+        let id = id.into();
+        self.jobs = self
+            .jobs
+            .into_iter()
+            .map(|job| job.with_batch_id(Some(id.clone())))
+            .collect();
+        self.id = id;
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn with_job(mut self, job: Job) -> Self {
+        self.jobs.push(job.with_batch_id(Some(self.id.clone())));
+        self
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// Job published once all the jobs of the batch finished, whatever their outcome.
+    pub fn with_on_complete(mut self, job: Job) -> Self {
+        self.on_complete = Some(job);
+        self
+    }
+
+    pub fn on_complete(&self) -> &Option<Job> {
+        &self.on_complete
+    }
+
+    /// Job published once all the jobs of the batch succeeded.
+    pub fn with_on_success(mut self, job: Job) -> Self {
+        self.on_success = Some(job);
+        self
+    }
+
+    pub fn on_success(&self) -> &Option<Job> {
+        &self.on_success
+    }
+
+    /// Job published once all the jobs of the batch finished and at least one of them exhausted
+    /// its attempts.
+    pub fn with_on_failure(mut self, job: Job) -> Self {
+        self.on_failure = Some(job);
+        self
+    }
+
+    pub fn on_failure(&self) -> &Option<Job> {
+        &self.on_failure
+    }
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct BatchProgress {
+    pub total: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    pub cancelled: u64,
+    /// Whether the batch finished and its callbacks were published.
+    pub finished: bool,
+}
+
+impl BatchProgress {
+    /// Jobs of the batch that did not finish yet.
+    pub fn pending(&self) -> u64 {
+        self.total
+            .saturating_sub(self.succeeded + self.failed + self.cancelled)
+    }
+}
@@ -4,12 +4,14 @@ use crate::JobHandler;
 
 pub struct Consumer {
     handlers: HashMap<String, HashMap<String, Box<dyn JobHandler>>>,
+    compensations: HashMap<String, HashMap<String, String>>,
 }
 
 impl Consumer {
     pub fn new() -> Self {
         Self {
             handlers: HashMap::new(),
+            compensations: HashMap::new(),
         }
     }
 
@@ -29,4 +31,33 @@ impl Consumer {
     pub fn handlers(&self) -> &HashMap<String, HashMap<String, Box<dyn JobHandler>>> {
         &self.handlers
     }
+
+    /// Register the job kind enqueued in the same queue to compensate a completed saga step of
+    /// `kind` when a later step of the saga fails permanently.
+    pub fn with_compensation<Q, K, C>(mut self, queue: Q, kind: K, compensation_kind: C) -> Self
+    where
+        Q: Into<String>,
+        K: Into<String>,
+        C: Into<String>,
+    {
+        self.compensations
+            .entry(queue.into())
+            .or_default()
+            .insert(kind.into(), compensation_kind.into());
+
+        self
+    }
+
+    pub fn compensation(&self, queue: &str, kind: &str) -> Option<&str> {
+        self.compensations
+            .get(queue)
+            .and_then(|kinds| kinds.get(kind))
+            .map(|k| &**k)
+    }
+}
+
+impl Default for Consumer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
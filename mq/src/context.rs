@@ -1,15 +1,21 @@
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 use tokio_util::sync::CancellationToken;
 
-use crate::{Error, Job};
+use crate::{Error, Job, Producer};
 
-#[derive(Debug)]
 pub struct Context {
     job: Job,
     cancellation_token: CancellationToken,
+    producer: Option<Arc<dyn Producer>>,
+    enqueued: Arc<Mutex<Vec<Job>>>,
 }
 
 impl Context {
@@ -17,9 +23,16 @@ impl Context {
         Self {
             job,
             cancellation_token,
+            producer: None,
+            enqueued: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
+    pub fn with_producer(mut self, producer: Option<Arc<dyn Producer>>) -> Self {
+        self.producer = producer;
+        self
+    }
+
     pub fn id(&self) -> &str {
         self.job.id()
     }
@@ -43,10 +56,42 @@ impl Context {
         Ok(serde_json::from_value::<T>(self.job.payload)?)
     }
 
+    pub fn headers(&self) -> &BTreeMap<String, Value> {
+        self.job.headers()
+    }
+
+    pub fn header(&self, key: &str) -> Option<&Value> {
+        self.job.header(key)
+    }
+
+    /// Deserialize the header, returning `None` when the job does not carry it.
+    pub fn deserialize_header<T>(&self, key: &str) -> Result<Option<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.job
+            .header(key)
+            .map(|value| T::deserialize(value).map_err(Error::from))
+            .transpose()
+    }
+
     pub fn error_reason(&self) -> &Option<Value> {
         self.job.error_reason()
     }
 
+    pub fn workflow_id(&self) -> &Option<String> {
+        self.job.workflow_id()
+    }
+
+    pub fn batch_id(&self) -> &Option<String> {
+        self.job.batch_id()
+    }
+
+    /// Outputs of the completed dependencies keyed by job id.
+    pub fn dependency_outputs(&self) -> &BTreeMap<String, Value> {
+        self.job.dependency_outputs()
+    }
+
     pub fn lease_time(&self) -> &Duration {
         self.job.lease_time()
     }
@@ -54,4 +99,39 @@ impl Context {
     pub fn cancellation_token(&self) -> &CancellationToken {
         &self.cancellation_token
     }
+
+    pub fn producer(&self) -> &Option<Arc<dyn Producer>> {
+        &self.producer
+    }
+
+    /// Enqueue a follow-up job.
+    ///
+    /// Enqueued jobs are only published once the current job completes with success and are
+    /// discarded otherwise. Backends that support transactions publish them atomically with the
+    /// completion of the current job. Requires the worker to be configured with a producer.
+    pub fn enqueue(&self, job: Job) -> Result<(), Error> {
+        if self.producer.is_none() {
+            return Err(Error::NotSupported(
+                "enqueue requires the worker to be configured with a producer".into(),
+            ));
+        }
+
+        self.enqueued.lock().unwrap().push(job);
+        Ok(())
+    }
+
+    pub(crate) fn enqueued(&self) -> Arc<Mutex<Vec<Job>>> {
+        self.enqueued.clone()
+    }
+}
+
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("job", &self.job)
+            .field("cancellation_token", &self.cancellation_token)
+            .field("producer", &self.producer.is_some())
+            .field("enqueued", &self.enqueued)
+            .finish()
+    }
 }
@@ -8,6 +8,14 @@ pub enum Error {
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::Error),
 
+    /// A job with the same `unique_key` is active, holds its id.
+    #[error("Duplicate job: {0}")]
+    DuplicateJob(String),
+
+    /// A job depends on a job that does not exist, holds its id.
+    #[error("Unknown dependency: {0}")]
+    UnknownDependency(String),
+
     #[error("Not supported error: {0}")]
     NotSupported(String),
 
@@ -1,12 +1,14 @@
-use std::time::Duration;
+use std::{collections::BTreeMap, time::Duration};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::{serde_as, DurationSeconds};
 use time::OffsetDateTime;
 
+use crate::{DependencyFailure, SagaStep, UniqueKeyConflict};
+
 #[serde_as]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Job {
     id: String,
     queue: String,
@@ -17,6 +19,9 @@ pub struct Job {
     updated_at: Option<OffsetDateTime>,
     #[serde(with = "time::serde::iso8601::option")]
     scheduled_at: Option<OffsetDateTime>,
+    /// Delay set by `with_schedule_in`, for backends that schedule relative to their own clock.
+    #[serde(skip)]
+    schedule_in: Option<Duration>,
     pub(crate) payload: Value,
     error_reason: Option<Value>,
     attempts: u16,
@@ -26,6 +31,29 @@ pub struct Job {
     /// Higher priority will get polled first.
     priority: u8,
     unique_key: Option<String>,
+    #[serde(default)]
+    on_unique_key_conflict: UniqueKeyConflict,
+    workflow_id: Option<String>,
+    batch_id: Option<String>,
+    /// Ids of the jobs that must complete before this job is polled.
+    #[serde(default)]
+    depends_on: Vec<String>,
+    #[serde(default)]
+    on_dependency_failure: DependencyFailure,
+    /// Outputs of the completed dependencies keyed by job id.
+    #[serde(default)]
+    dependency_outputs: BTreeMap<String, Value>,
+    /// Steps of the saga that completed before this job, compensated in reverse order when this
+    /// job exhausts its attempts.
+    #[serde(default)]
+    saga_steps: Vec<SagaStep>,
+    /// Whether the backend keeps the outcome of the job once it finished.
+    #[serde(default)]
+    track_outcome: bool,
+    /// Metadata carried along with the job without being part of its payload, e.g. a tenant id,
+    /// a correlation id or the trace context of the publisher.
+    #[serde(default)]
+    headers: BTreeMap<String, Value>,
 }
 
 impl Job {
@@ -43,11 +71,21 @@ impl Job {
             created_at: None,
             updated_at: None,
             scheduled_at: None,
+            schedule_in: None,
             attempts: 0,
             max_attempts: 3,
             lease_time: Duration::from_secs(30),
             priority: 0,
             unique_key: None,
+            on_unique_key_conflict: UniqueKeyConflict::default(),
+            workflow_id: None,
+            batch_id: None,
+            depends_on: Vec::new(),
+            on_dependency_failure: DependencyFailure::default(),
+            dependency_outputs: BTreeMap::new(),
+            saga_steps: Vec::new(),
+            track_outcome: false,
+            headers: BTreeMap::new(),
         }
     }
 
@@ -119,16 +157,19 @@ impl Job {
 
     pub fn with_schedule_at(mut self, date_time: OffsetDateTime) -> Self {
         self.scheduled_at = Some(date_time);
+        self.schedule_in = None;
         self
     }
 
     pub fn with_schedule_now(mut self) -> Self {
         self.scheduled_at = None;
+        self.schedule_in = None;
         self
     }
 
     pub fn with_schedule_in(mut self, duration: Duration) -> Self {
         self.scheduled_at = Some(OffsetDateTime::now_utc() + duration);
+        self.schedule_in = Some(duration);
         self
     }
 
@@ -136,6 +177,13 @@ impl Job {
         &self.scheduled_at
     }
 
+    /// Delay given to [`with_schedule_in`](Self::with_schedule_in). `scheduled_at` is computed
+    /// with the clock of the publisher, backends that use the clock of the database server
+    /// schedule the job this long after they receive it instead.
+    pub fn schedule_in(&self) -> &Option<Duration> {
+        &self.schedule_in
+    }
+
     pub fn with_error_reason(mut self, error_reason: Option<Value>) -> Self {
         self.error_reason = error_reason;
         self
@@ -162,4 +210,118 @@ impl Job {
         self.unique_key = unique_key;
         self
     }
+
+    pub fn on_unique_key_conflict(&self) -> UniqueKeyConflict {
+        self.on_unique_key_conflict
+    }
+
+    /// What publishing the job does when an active job with the same `unique_key` exists.
+    pub fn with_on_unique_key_conflict(
+        mut self,
+        on_unique_key_conflict: UniqueKeyConflict,
+    ) -> Self {
+        self.on_unique_key_conflict = on_unique_key_conflict;
+        self
+    }
+
+    pub fn workflow_id(&self) -> &Option<String> {
+        &self.workflow_id
+    }
+
+    pub fn with_workflow_id(mut self, workflow_id: Option<String>) -> Self {
+        self.workflow_id = workflow_id;
+        self
+    }
+
+    pub fn batch_id(&self) -> &Option<String> {
+        &self.batch_id
+    }
+
+    pub fn with_batch_id(mut self, batch_id: Option<String>) -> Self {
+        self.batch_id = batch_id;
+        self
+    }
+
+    pub fn depends_on(&self) -> &[String] {
+        &self.depends_on
+    }
+
+    /// Ids of the jobs that must complete before this job runs. As completed jobs are deleted, a
+    /// dependency that does not exist when the job is published, and is not published along with
+    /// it, e.g. in the same [`Workflow`](crate::Workflow), is treated as completed.
+    pub fn with_depends_on<I, S>(mut self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.depends_on = ids.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn on_dependency_failure(&self) -> DependencyFailure {
+        self.on_dependency_failure
+    }
+
+    pub fn with_on_dependency_failure(mut self, on_dependency_failure: DependencyFailure) -> Self {
+        self.on_dependency_failure = on_dependency_failure;
+        self
+    }
+
+    pub fn dependency_outputs(&self) -> &BTreeMap<String, Value> {
+        &self.dependency_outputs
+    }
+
+    pub fn saga_steps(&self) -> &[SagaStep] {
+        &self.saga_steps
+    }
+
+    pub fn with_saga_steps(mut self, saga_steps: Vec<SagaStep>) -> Self {
+        self.saga_steps = saga_steps;
+        self
+    }
+
+    pub fn track_outcome(&self) -> bool {
+        self.track_outcome
+    }
+
+    pub fn with_track_outcome(mut self, track_outcome: bool) -> Self {
+        self.track_outcome = track_outcome;
+        self
+    }
+
+    pub fn headers(&self) -> &BTreeMap<String, Value> {
+        &self.headers
+    }
+
+    pub fn header(&self, key: &str) -> Option<&Value> {
+        self.headers.get(key)
+    }
+
+    pub fn with_header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    /// Adds the headers, replacing the existing values of the same keys.
+    pub fn with_headers<I, K, V>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        self.headers
+            .extend(headers.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Captures the trace context of the current span in the headers unless the job already
+    /// carries one. Does nothing without the `opentelemetry` feature.
+    pub fn with_trace_context(mut self) -> Self {
+        crate::inject_trace_context(&mut self.headers);
+        self
+    }
 }
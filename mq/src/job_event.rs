@@ -0,0 +1,131 @@
+use futures::{stream::BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Stream of job lifecycle events.
+pub type JobEventStream = BoxStream<'static, Result<JobEvent, crate::Error>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobEvent {
+    pub queue: String,
+    pub kind: String,
+    pub id: String,
+    #[serde(with = "time::serde::iso8601")]
+    pub at: OffsetDateTime,
+    #[serde(flatten)]
+    pub event: JobEventType,
+}
+
+impl JobEvent {
+    pub fn new<Q, K, I>(queue: Q, kind: K, id: I, event: JobEventType) -> Self
+    where
+        Q: Into<String>,
+        K: Into<String>,
+        I: Into<String>,
+    {
+        Self {
+            queue: queue.into(),
+            kind: kind.into(),
+            id: id.into(),
+            at: OffsetDateTime::now_utc(),
+            event,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobEventType {
+    Published,
+    Started {
+        attempt: u16,
+    },
+    Succeeded,
+    Failed {
+        attempt: u16,
+        #[serde(default)]
+        error: Value,
+    },
+    Cancelled {
+        #[serde(default)]
+        message: Option<String>,
+    },
+    /// The job exhausted its attempts.
+    DeadLettered {
+        #[serde(default)]
+        error: Value,
+    },
+}
+
+/// Selects the events of a queue, kind or job id. An empty filter matches every event.
+#[derive(Debug, Default, Clone)]
+pub struct JobEventFilter {
+    queue: Option<String>,
+    kind: Option<String>,
+    id: Option<String>,
+}
+
+impl JobEventFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_queue<S: Into<String>>(mut self, queue: S) -> Self {
+        self.queue = Some(queue.into());
+        self
+    }
+
+    pub fn queue(&self) -> &Option<String> {
+        &self.queue
+    }
+
+    pub fn with_kind<S: Into<String>>(mut self, kind: S) -> Self {
+        self.kind = Some(kind.into());
+        self
+    }
+
+    pub fn kind(&self) -> &Option<String> {
+        &self.kind
+    }
+
+    pub fn with_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn id(&self) -> &Option<String> {
+        &self.id
+    }
+
+    pub fn matches(&self, event: &JobEvent) -> bool {
+        self.queue.as_ref().is_none_or(|q| *q == event.queue)
+            && self.kind.as_ref().is_none_or(|k| *k == event.kind)
+            && self.id.as_ref().is_none_or(|id| *id == event.id)
+    }
+}
+
+/// Turn a broadcast receiver into a filtered event stream. Events missed by a lagging
+/// subscriber are skipped.
+pub(crate) fn broadcast_stream(
+    receiver: broadcast::Receiver<JobEvent>,
+    filter: JobEventFilter,
+) -> JobEventStream {
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((Ok(event), receiver)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .filter(move |event| {
+        futures::future::ready(match event {
+            Ok(event) => filter.matches(event),
+            Err(_) => true,
+        })
+    })
+    .boxed()
+}
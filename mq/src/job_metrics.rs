@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::Job;
+
+/// Jobs completed successfully, labelled by `queue` and `kind`.
+pub const JOBS_SUCCEEDED: &str = "mq_jobs_succeeded_total";
+/// Failed attempts, labelled by `queue` and `kind`.
+pub const JOBS_FAILED: &str = "mq_jobs_failed_total";
+/// Jobs cancelled by their handler, labelled by `queue` and `kind`.
+pub const JOBS_CANCELLED: &str = "mq_jobs_cancelled_total";
+/// Jobs that exhausted their attempts, labelled by `queue` and `kind`.
+pub const JOBS_EXHAUSTED: &str = "mq_jobs_exhausted_total";
+/// Jobs currently processed by the worker, labelled by `queue` and `kind`.
+pub const JOBS_IN_FLIGHT: &str = "mq_jobs_in_flight";
+/// Duration of the handlers, labelled by `queue` and `kind`.
+pub const JOB_DURATION: &str = "mq_job_duration_seconds";
+/// Time between `scheduled_at` and the start of the attempt, labelled by `queue` and `kind`.
+pub const JOB_LAG: &str = "mq_job_lag_seconds";
+/// Duration of `JobProcessor::poll_next_job`.
+pub const POLL_DURATION: &str = "mq_poll_duration_seconds";
+/// Jobs of a queue by state, labelled by `queue` and `state`.
+pub const QUEUE_DEPTH: &str = "mq_queue_depth";
+
+/// Registers the descriptions of the metrics emitted by mq with the installed recorder.
+pub fn describe_metrics() {
+    describe_counter!(JOBS_SUCCEEDED, Unit::Count, "Jobs completed successfully");
+    describe_counter!(JOBS_FAILED, Unit::Count, "Failed job attempts");
+    describe_counter!(
+        JOBS_CANCELLED,
+        Unit::Count,
+        "Jobs cancelled by their handler"
+    );
+    describe_counter!(
+        JOBS_EXHAUSTED,
+        Unit::Count,
+        "Jobs that exhausted their attempts"
+    );
+    describe_gauge!(JOBS_IN_FLIGHT, Unit::Count, "Jobs currently processed");
+    describe_histogram!(JOB_DURATION, Unit::Seconds, "Duration of the job handlers");
+    describe_histogram!(
+        JOB_LAG,
+        Unit::Seconds,
+        "Time between the scheduled time of a job and the start of its attempt"
+    );
+    describe_histogram!(
+        POLL_DURATION,
+        Unit::Seconds,
+        "Duration of polling the next job"
+    );
+    describe_gauge!(QUEUE_DEPTH, Unit::Count, "Jobs of a queue by state");
+}
+
+/// Jobs of a queue by state.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct QueueDepth {
+    /// Jobs ready to be polled, including the ones waiting to be retried.
+    pub ready: u64,
+    /// Jobs scheduled in the future or waiting for their dependencies.
+    pub scheduled: u64,
+    /// Jobs currently leased by a worker.
+    pub running: u64,
+    /// Jobs that exhausted their attempts.
+    pub dead: u64,
+}
+
+impl QueueDepth {
+    /// Sets the [`QUEUE_DEPTH`] gauges of the queue. Called by the worker when enabled with
+    /// [`crate::Worker::with_queue_depth_interval`], otherwise call it with the depth read with
+    /// [`crate::Producer::queue_depth`].
+    pub fn record(&self, queue: &str) {
+        for (state, value) in [
+            ("ready", self.ready),
+            ("scheduled", self.scheduled),
+            ("running", self.running),
+            ("dead", self.dead),
+        ] {
+            metrics::gauge!(QUEUE_DEPTH, "queue" => queue.to_owned(), "state" => state)
+                .set(value as f64);
+        }
+    }
+}
+
+pub(crate) enum JobMetric {
+    Succeeded,
+    Failed { exhausted: bool },
+    Cancelled,
+}
+
+/// Tracks the in-flight gauge of a job until dropped.
+pub(crate) struct InFlight {
+    queue: String,
+    kind: String,
+}
+
+impl InFlight {
+    pub(crate) fn start(job: &Job) -> Self {
+        let queue = job.queue().to_owned();
+        let kind = job.kind().to_owned();
+
+        if let Some(scheduled_at) = job.scheduled_at() {
+            let lag = (OffsetDateTime::now_utc() - *scheduled_at).as_seconds_f64();
+            metrics::histogram!(JOB_LAG, "queue" => queue.clone(), "kind" => kind.clone())
+                .record(lag.max(0.0));
+        }
+        metrics::gauge!(JOBS_IN_FLIGHT, "queue" => queue.clone(), "kind" => kind.clone())
+            .increment(1.0);
+
+        Self { queue, kind }
+    }
+
+    pub(crate) fn finish(&self, duration: Duration, metric: JobMetric) {
+        let labels = [("queue", self.queue.clone()), ("kind", self.kind.clone())];
+
+        metrics::histogram!(JOB_DURATION, &labels).record(duration.as_secs_f64());
+        match metric {
+            JobMetric::Succeeded => metrics::counter!(JOBS_SUCCEEDED, &labels).increment(1),
+            JobMetric::Cancelled => metrics::counter!(JOBS_CANCELLED, &labels).increment(1),
+            JobMetric::Failed { exhausted } => {
+                metrics::counter!(JOBS_FAILED, &labels).increment(1);
+                if exhausted {
+                    metrics::counter!(JOBS_EXHAUSTED, &labels).increment(1);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        metrics::gauge!(JOBS_IN_FLIGHT, "queue" => self.queue.clone(), "kind" => self.kind.clone())
+            .decrement(1.0);
+    }
+}
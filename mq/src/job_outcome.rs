@@ -0,0 +1,12 @@
+use serde_json::Value;
+
+/// How a job finished.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobOutcome {
+    /// The job succeeded with the output returned by its handler, if any.
+    Succeeded(Option<Value>),
+    /// The job was cancelled with an optional message.
+    Cancelled(Option<String>),
+    /// The job exhausted its attempts with the reason of the last failure.
+    Failed(Option<Value>),
+}
@@ -1,7 +1,8 @@
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 use serde_json::Value;
 
-use crate::{Error, Job};
+use crate::{Error, Job, Producer};
 
 #[async_trait]
 pub trait JobProcessor: Send + Sync {
@@ -11,6 +12,17 @@ pub trait JobProcessor: Send + Sync {
     /// Higher priority will be polled first.
     async fn poll_next_job(&self, queues: &[&str]) -> Result<Option<Job>, Error>;
 
+    /// Stream yielding whenever new jobs may be available in the queues, so the worker polls
+    /// right away instead of waiting for the next poll interval.
+    ///
+    /// The default implementation returns `None` and the worker only polls on its interval.
+    async fn job_notifications(
+        &self,
+        _queues: &[&str],
+    ) -> Result<Option<BoxStream<'static, ()>>, Error> {
+        Ok(None)
+    }
+
     /// Complete the job with success.
     async fn complete_job_with_success(
         &self,
@@ -19,6 +31,42 @@ pub trait JobProcessor: Send + Sync {
         id: &str,
     ) -> Result<(), Error>;
 
+    /// Complete the job with success and hand the output to the jobs depending on it.
+    ///
+    /// The default implementation discards the output.
+    async fn complete_job_with_output(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        _output: Value,
+    ) -> Result<(), Error> {
+        self.complete_job_with_success(queue, kind, id).await
+    }
+
+    /// Complete the job with success and publish the jobs enqueued by its handler.
+    ///
+    /// The default implementation publishes the jobs with the producer before completing the
+    /// job, which is not atomic. Backends that support transactions should override it.
+    async fn complete_job_with_success_and_publish(
+        &self,
+        queue: &str,
+        kind: &str,
+        id: &str,
+        output: Option<Value>,
+        jobs: Vec<Job>,
+        producer: &dyn Producer,
+    ) -> Result<(), Error> {
+        for job in jobs {
+            producer.publish(job).await?;
+        }
+
+        match output {
+            Some(output) => self.complete_job_with_output(queue, kind, id, output).await,
+            None => self.complete_job_with_success(queue, kind, id).await,
+        }
+    }
+
     /// Complete the job with cancel.
     async fn complete_job_with_cancelled(
         &self,
@@ -31,4 +79,12 @@ pub trait JobProcessor: Send + Sync {
     /// Fail the job.
     async fn fail_job(&self, queue: &str, kind: &str, id: &str, reason: Value)
         -> Result<(), Error>;
+
+    /// Fail the jobs of the queues whose last attempt ended by lease expiry, e.g. as their worker
+    /// died, and return them so the worker can compensate their sagas.
+    ///
+    /// The default implementation returns no jobs, as only backends supporting sagas need it.
+    async fn fail_expired_jobs(&self, _queues: &[&str]) -> Result<Vec<Job>, Error> {
+        Ok(Vec::new())
+    }
 }
@@ -1,4 +1,8 @@
+use serde_json::Value;
+
 pub enum JobResult {
     CompleteWithSuccess,
+    /// Complete with success handing the output to the jobs depending on this job.
+    CompleteWithOutput(Value),
     CompleteWithCancelled(Option<String>),
 }
@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use time::OffsetDateTime;
+
+use crate::{Error, Job};
+
+const DEFAULT_PAGE_LIMIT: usize = 100;
+
+/// State of a job, as counted by [`QueueDepth`](crate::QueueDepth).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Ready to be polled, including the jobs waiting to be retried.
+    Ready,
+    /// Scheduled in the future or waiting for its dependencies.
+    Scheduled,
+    /// Leased by a worker.
+    Running,
+    /// Exhausted its attempts.
+    Dead,
+}
+
+/// Selects the jobs to list or count. An empty filter matches every job.
+#[derive(Debug, Default, Clone)]
+pub struct JobFilter {
+    queue: Option<String>,
+    kind: Option<String>,
+    state: Option<JobState>,
+    unique_key: Option<String>,
+    scheduled_after: Option<OffsetDateTime>,
+    scheduled_before: Option<OffsetDateTime>,
+    min_attempts: Option<u16>,
+    max_attempts: Option<u16>,
+    has_error: Option<bool>,
+    headers: BTreeMap<String, Value>,
+}
+
+impl JobFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_queue<S: Into<String>>(mut self, queue: S) -> Self {
+        self.queue = Some(queue.into());
+        self
+    }
+
+    pub fn queue(&self) -> &Option<String> {
+        &self.queue
+    }
+
+    pub fn with_kind<S: Into<String>>(mut self, kind: S) -> Self {
+        self.kind = Some(kind.into());
+        self
+    }
+
+    pub fn kind(&self) -> &Option<String> {
+        &self.kind
+    }
+
+    pub fn with_state(mut self, state: JobState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    pub fn state(&self) -> Option<JobState> {
+        self.state
+    }
+
+    pub fn with_unique_key<S: Into<String>>(mut self, unique_key: S) -> Self {
+        self.unique_key = Some(unique_key.into());
+        self
+    }
+
+    pub fn unique_key(&self) -> &Option<String> {
+        &self.unique_key
+    }
+
+    /// Jobs scheduled at or after the time.
+    pub fn with_scheduled_after(mut self, scheduled_after: OffsetDateTime) -> Self {
+        self.scheduled_after = Some(scheduled_after);
+        self
+    }
+
+    pub fn scheduled_after(&self) -> &Option<OffsetDateTime> {
+        &self.scheduled_after
+    }
+
+    /// Jobs scheduled before the time.
+    pub fn with_scheduled_before(mut self, scheduled_before: OffsetDateTime) -> Self {
+        self.scheduled_before = Some(scheduled_before);
+        self
+    }
+
+    pub fn scheduled_before(&self) -> &Option<OffsetDateTime> {
+        &self.scheduled_before
+    }
+
+    /// Jobs attempted at least `min_attempts` times.
+    pub fn with_min_attempts(mut self, min_attempts: u16) -> Self {
+        self.min_attempts = Some(min_attempts);
+        self
+    }
+
+    pub fn min_attempts(&self) -> Option<u16> {
+        self.min_attempts
+    }
+
+    /// Jobs attempted at most `max_attempts` times.
+    pub fn with_max_attempts(mut self, max_attempts: u16) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    pub fn max_attempts(&self) -> Option<u16> {
+        self.max_attempts
+    }
+
+    /// Jobs whose last attempt failed with an error, or jobs without one.
+    pub fn with_has_error(mut self, has_error: bool) -> Self {
+        self.has_error = Some(has_error);
+        self
+    }
+
+    pub fn has_error(&self) -> Option<bool> {
+        self.has_error
+    }
+
+    /// Jobs having the header with the value, in addition to the headers already added.
+    pub fn with_header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    /// Jobs having all the headers with their values, other headers are ignored.
+    pub fn headers(&self) -> &BTreeMap<String, Value> {
+        &self.headers
+    }
+}
+
+/// Page of jobs to list: at most `limit` jobs following the `cursor` returned with the previous
+/// page.
+#[derive(Debug, Clone)]
+pub struct Page {
+    cursor: Option<String>,
+    limit: usize,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self {
+            cursor: None,
+            limit: DEFAULT_PAGE_LIMIT,
+        }
+    }
+}
+
+impl Page {
+    /// First page of at most 100 jobs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Page following the one that returned the cursor.
+    pub fn with_cursor<S: Into<String>>(mut self, cursor: S) -> Self {
+        self.cursor = Some(cursor.into());
+        self
+    }
+
+    pub fn cursor(&self) -> &Option<String> {
+        &self.cursor
+    }
+}
+
+/// Jobs of a page, with the cursor of the next page if there are more jobs.
+#[derive(Debug, Clone)]
+pub struct JobPage {
+    pub jobs: Vec<Job>,
+    pub next_cursor: Option<String>,
+}
+
+/// Applies the JSON merge patch (RFC 7396) to the target: the members of the patch replace the
+/// ones of the target, recursively for objects, and its `null` members remove them.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!("target is an object");
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Access to the jobs of a backend, for inspection and admin tooling.
+///
+/// The admin operations return the updated job, or Ok(None) if it does not exist. They apply to
+/// the next attempt of a job that is running.
+#[async_trait]
+pub trait JobStore: Send + Sync {
+    /// Get the job with the id. Returns Ok(None) if it does not exist.
+    async fn get_job(&self, id: &str) -> Result<Option<Job>, Error>;
+
+    /// List the jobs matching the filter, one page at a time. Jobs are listed in a stable order,
+    /// so the cursor of a page resumes after its last job even if jobs were added or removed
+    /// meanwhile.
+    async fn list_jobs(&self, filter: &JobFilter, page: &Page) -> Result<JobPage, Error>;
+
+    /// Count the jobs matching the filter.
+    async fn count(&self, filter: &JobFilter) -> Result<u64, Error>;
+
+    /// Make the job due now. With `reset_attempts`, the job gets all its attempts again, which
+    /// retries a dead job. Without it, a dead job stays dead. Either way the job keeps the
+    /// `error_reason` of its last failed attempt until its next attempt fails.
+    async fn retry_now(&self, _id: &str, _reset_attempts: bool) -> Result<Option<Job>, Error> {
+        Err(Error::NotSupported("retry_now".into()))
+    }
+
+    /// Schedule the job at the time.
+    async fn reschedule(
+        &self,
+        _id: &str,
+        _scheduled_at: OffsetDateTime,
+    ) -> Result<Option<Job>, Error> {
+        Err(Error::NotSupported("reschedule".into()))
+    }
+
+    async fn set_priority(&self, _id: &str, _priority: u8) -> Result<Option<Job>, Error> {
+        Err(Error::NotSupported("set_priority".into()))
+    }
+
+    /// Set the maximum attempts of the job. Raising them above its attempts retries a dead job.
+    async fn set_max_attempts(&self, _id: &str, _max_attempts: u16) -> Result<Option<Job>, Error> {
+        Err(Error::NotSupported("set_max_attempts".into()))
+    }
+
+    /// Apply the JSON merge patch to the payload of the job, see [`merge_patch`].
+    async fn patch_payload(&self, _id: &str, _patch: Value) -> Result<Option<Job>, Error> {
+        Err(Error::NotSupported("patch_payload".into()))
+    }
+
+    /// Release the lease of the job, e.g. held by a worker that died, so it is polled again
+    /// without waiting for the lease to expire. The attempt remains counted.
+    async fn release_lock(&self, _id: &str) -> Result<Option<Job>, Error> {
+        Err(Error::NotSupported("release_lock".into()))
+    }
+}
@@ -1,19 +1,45 @@
+mod batch;
 mod consumer;
 mod context;
 mod errors;
 mod job;
+mod job_event;
 mod job_handler;
+mod job_metrics;
+mod job_outcome;
 mod job_processor;
 mod job_result;
+mod job_store;
+#[cfg(feature = "prometheus")]
+mod metrics_server;
 mod producer;
+mod publish_outcome;
+mod saga;
+mod trace_context;
 mod worker;
+mod worker_health;
+mod worker_hooks;
+mod workflow;
 
+pub use batch::*;
 pub use consumer::*;
 pub use context::*;
 pub use errors::*;
 pub use job::*;
+pub use job_event::*;
 pub use job_handler::*;
+pub use job_metrics::*;
+pub use job_outcome::*;
 pub use job_processor::*;
 pub use job_result::*;
+pub use job_store::*;
+#[cfg(feature = "prometheus")]
+pub use metrics_server::*;
 pub use producer::*;
+pub use publish_outcome::*;
+pub use saga::*;
+pub use trace_context::{inject_trace_context, TRACEPARENT_HEADER};
 pub use worker::*;
+pub use worker_health::*;
+pub use worker_hooks::*;
+pub use workflow::*;
@@ -0,0 +1,120 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use http_body_util::Full;
+use hyper::{body::Bytes, server::conn::http1, service::service_fn, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use crate::{describe_metrics, Error, WorkerHealth};
+
+/// Small HTTP server exposing the metrics in the Prometheus text format on `/metrics`, a liveness
+/// probe on `/healthz` and a readiness probe on `/readyz`.
+///
+/// `/readyz` succeeds when at least one worker is registered with [`MetricsServer::with_worker`]
+/// and every registered worker is running and its last poll succeeded.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    handle: PrometheusHandle,
+    workers: Vec<WorkerHealth>,
+    cancellation_token: CancellationToken,
+}
+
+impl MetricsServer {
+    /// Installs the Prometheus recorder as the global metrics recorder.
+    pub fn install<A: Into<SocketAddr>>(addr: A) -> Result<Self, Error> {
+        let handle = PrometheusBuilder::new()
+            .install_recorder()
+            .map_err(|e| Error::OtherError(Box::new(e)))?;
+        describe_metrics();
+        Ok(Self::new(addr, handle))
+    }
+
+    /// Serves the metrics of an already installed Prometheus recorder.
+    pub fn new<A: Into<SocketAddr>>(addr: A, handle: PrometheusHandle) -> Self {
+        Self {
+            addr: addr.into(),
+            handle,
+            workers: Vec::new(),
+            cancellation_token: CancellationToken::new(),
+        }
+    }
+
+    pub fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    pub fn handle(&self) -> &PrometheusHandle {
+        &self.handle
+    }
+
+    pub fn with_worker(mut self, health: WorkerHealth) -> Self {
+        self.workers.push(health);
+        self
+    }
+
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation_token
+    }
+
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = cancellation_token;
+        self
+    }
+
+    pub async fn run(self) -> Result<(), Error> {
+        let listener = TcpListener::bind(self.addr).await?;
+        self.serve(listener).await
+    }
+
+    /// Serves on an already bound listener instead of binding `addr`, e.g. to listen on a port
+    /// picked by the OS.
+    pub async fn serve(self, listener: TcpListener) -> Result<(), Error> {
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => accepted?.0,
+                _ = self.cancellation_token.cancelled() => break,
+            };
+
+            let handle = self.handle.clone();
+            let workers = self.workers.clone();
+            let service = service_fn(move |request: Request<hyper::body::Incoming>| {
+                let response = respond(request.uri().path(), &handle, &workers);
+                async move { Ok::<_, Infallible>(response) }
+            });
+
+            tokio::spawn(async move {
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    debug!("Metrics connection failed with {:?}", e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn respond(
+    path: &str,
+    handle: &PrometheusHandle,
+    workers: &[WorkerHealth],
+) -> Response<Full<Bytes>> {
+    let (status, body) = match path {
+        "/metrics" => (StatusCode::OK, handle.render()),
+        "/healthz" => (StatusCode::OK, "ok".to_string()),
+        "/readyz" if !workers.is_empty() && workers.iter().all(WorkerHealth::is_ready) => {
+            (StatusCode::OK, "ok".to_string())
+        }
+        "/readyz" => (StatusCode::SERVICE_UNAVAILABLE, "not ready".to_string()),
+        _ => (StatusCode::NOT_FOUND, "not found".to_string()),
+    };
+
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+    response
+}
@@ -1,11 +1,120 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
+use tokio::time::Instant;
+
+use crate::{
+    Batch, BatchProgress, Error, Job, JobEventFilter, JobEventStream, JobOutcome, PublishOutcome,
+    QueueDepth, Workflow, WorkflowProgress,
+};
 
-use crate::{Error, Job};
+const OUTCOME_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[async_trait]
 pub trait Producer: Send + Sync {
-    async fn publish(&self, job: Job) -> Result<(), Error>;
+    /// Publish the job. A job whose `unique_key` is held by an active job is handled according to
+    /// its [`UniqueKeyConflict`](crate::UniqueKeyConflict) strategy.
+    async fn publish(&self, job: Job) -> Result<PublishOutcome, Error>;
     async fn exists(&self, queue: &str, kind: &str, id: &str) -> Result<bool, Error>;
     async fn cancel_by_id(&self, queue: &str, kind: &str, id: &str) -> Result<(), Error>;
     async fn cancel_by_unique_key(&self, queue: &str, kind: &str, key: &str) -> Result<(), Error>;
+
+    /// Publish the jobs atomically, so none of them is published if any fails. Returns the
+    /// outcome of each job in order.
+    async fn publish_many(&self, _jobs: Vec<Job>) -> Result<Vec<PublishOutcome>, Error> {
+        Err(Error::NotSupported("publish_many".into()))
+    }
+
+    /// Publish the jobs of the workflow in the order they were added.
+    async fn publish_workflow(&self, workflow: Workflow) -> Result<(), Error> {
+        for job in workflow.into_jobs() {
+            self.publish(job).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Count the remaining jobs of the workflow.
+    async fn workflow_progress(&self, _workflow_id: &str) -> Result<WorkflowProgress, Error> {
+        Err(Error::NotSupported("workflow_progress".into()))
+    }
+
+    /// Count the jobs of the queue by state.
+    async fn queue_depth(&self, _queue: &str) -> Result<QueueDepth, Error> {
+        Err(Error::NotSupported("queue_depth".into()))
+    }
+
+    /// Publish the jobs of the batch and track them until all of them finished.
+    async fn publish_batch(&self, _batch: Batch) -> Result<(), Error> {
+        Err(Error::NotSupported("publish_batch".into()))
+    }
+
+    async fn batch_progress(&self, _batch_id: &str) -> Result<BatchProgress, Error> {
+        Err(Error::NotSupported("batch_progress".into()))
+    }
+
+    /// Get the outcome of a job published with outcome tracking. Returns Ok(None) while the job
+    /// has not finished.
+    async fn job_outcome(
+        &self,
+        _queue: &str,
+        _kind: &str,
+        _id: &str,
+    ) -> Result<Option<JobOutcome>, Error> {
+        Err(Error::NotSupported("job_outcome".into()))
+    }
+
+    /// Delete the outcomes of the jobs that finished more than `older_than` ago and return how
+    /// many were deleted. Outcomes are kept until deleted, so call it periodically.
+    async fn purge_outcomes(&self, _older_than: Duration) -> Result<u64, Error> {
+        Err(Error::NotSupported("purge_outcomes".into()))
+    }
+
+    /// Publish the job with outcome tracking and wait until it succeeds, is cancelled or exhausts
+    /// its attempts. Returns Ok(None) if the job did not finish within `timeout`, in which case
+    /// its outcome can still be polled with [`Producer::job_outcome`]. When the job conflicts with
+    /// an active job with the same `unique_key`, waits for the outcome of that job instead, which
+    /// is only known if it was published with outcome tracking. Returns
+    /// [`Error::NotSupported`] without publishing the job if the backend does not support
+    /// [`Producer::job_outcome`].
+    async fn publish_and_wait(
+        &self,
+        job: Job,
+        timeout: Duration,
+    ) -> Result<Option<JobOutcome>, Error> {
+        let queue = job.queue().to_owned();
+        let kind = job.kind().to_owned();
+        let id = job.id().to_owned();
+        let deadline = Instant::now() + timeout;
+
+        // fails with NotSupported before publishing on backends that do not track outcomes
+        self.job_outcome(&queue, &kind, &id).await?;
+        let published = self.publish(job.with_track_outcome(true)).await?;
+        let id = published.existing_id().map(str::to_owned).unwrap_or(id);
+
+        loop {
+            if let Some(outcome) = self.job_outcome(&queue, &kind, &id).await? {
+                return Ok(Some(outcome));
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+
+            tokio::time::sleep(OUTCOME_POLL_INTERVAL.min(deadline - now)).await;
+        }
+    }
+
+    /// Subscribe to the lifecycle events of the jobs matching the filter, across all the
+    /// producers and workers of the backend.
+    async fn subscribe(&self, _filter: JobEventFilter) -> Result<JobEventStream, Error> {
+        Err(Error::NotSupported("subscribe".into()))
+    }
+
+    /// Delete the lifecycle events recorded more than `older_than` ago and return how many were
+    /// deleted. Events are kept until deleted, so call it periodically.
+    async fn purge_events(&self, _older_than: Duration) -> Result<u64, Error> {
+        Err(Error::NotSupported("purge_events".into()))
+    }
 }
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// What publishing a job does when an active job with the same `unique_key` exists, i.e. a job of
+/// the same queue and kind that has attempts left.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UniqueKeyConflict {
+    /// Keep the existing job and skip the published one.
+    #[default]
+    Skip,
+    /// Overwrite the payload and the schedule of the existing job with the ones of the published
+    /// job. A job leased by a worker is kept as is and the published one skipped, as for `Skip`.
+    Replace,
+    /// Overwrite the schedule of the existing job with the one of the published job. A job leased
+    /// by a worker is kept as is and the published one skipped, as for `Skip`.
+    Reschedule,
+    /// Fail the publish with [`Error::DuplicateJob`](crate::Error::DuplicateJob).
+    Error,
+}
+
+impl UniqueKeyConflict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UniqueKeyConflict::Skip => "skip",
+            UniqueKeyConflict::Replace => "replace",
+            UniqueKeyConflict::Reschedule => "reschedule",
+            UniqueKeyConflict::Error => "error",
+        }
+    }
+}
+
+/// What publishing a job did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublishOutcome {
+    /// The job was created.
+    Published,
+    /// The job was skipped because of the active job with the same `unique_key`, or because that
+    /// job was leased by a worker when replacing or rescheduling it, holds its id.
+    Duplicate(String),
+    /// The active job with the same `unique_key` was replaced or rescheduled, holds its id.
+    Replaced(String),
+}
+
+impl PublishOutcome {
+    /// Id of the existing job the published job conflicted with.
+    pub fn existing_id(&self) -> Option<&str> {
+        match self {
+            PublishOutcome::Published => None,
+            PublishOutcome::Duplicate(id) | PublishOutcome::Replaced(id) => Some(id),
+        }
+    }
+
+    /// Returns [`Error::DuplicateJob`] instead of a `Duplicate` outcome for jobs published with
+    /// [`UniqueKeyConflict::Error`], for backends that skip these jobs like `Skip` ones.
+    pub fn check(self, on_unique_key_conflict: UniqueKeyConflict) -> Result<Self, Error> {
+        match self {
+            PublishOutcome::Duplicate(id) if on_unique_key_conflict == UniqueKeyConflict::Error => {
+                Err(Error::DuplicateJob(id))
+            }
+            outcome => Ok(outcome),
+        }
+    }
+}
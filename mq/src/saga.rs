@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Job, Workflow};
+
+/// Sequence of steps where each step runs after the previous one succeeded.
+///
+/// When a step exhausts its attempts, or the lease of its last attempt expires, the worker
+/// enqueues the compensating jobs registered with [`crate::Consumer::with_compensation`] for the
+/// completed steps in reverse order. The remaining steps are cancelled. Sagas need a backend
+/// supporting `depends_on`, others reject their steps with [`crate::Error::NotSupported`].
+#[derive(Debug)]
+pub struct Saga {
+    id: String,
+    steps: Vec<Job>,
+}
+
+impl Saga {
+    pub fn new() -> Self {
+        Self {
+            id: xid::new().to_string(),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn with_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = id.into();
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn with_step(mut self, job: Job) -> Self {
+        self.steps.push(job);
+        self
+    }
+
+    pub fn steps(&self) -> &[Job] {
+        &self.steps
+    }
+
+    /// Chain the steps into a workflow that can be published with
+    /// [`crate::Producer::publish_workflow`].
+    pub fn into_workflow(self) -> Workflow {
+        let mut workflow = Workflow::new().with_id(self.id);
+        let mut completed: Vec<SagaStep> = Vec::new();
+
+        for job in self.steps {
+            let step = SagaStep {
+                id: job.id().to_owned(),
+                queue: job.queue().to_owned(),
+                kind: job.kind().to_owned(),
+                payload: job.payload().to_owned(),
+            };
+
+            let job = match completed.last() {
+                Some(previous) => job.with_depends_on([previous.id.clone()]),
+                None => job,
+            };
+            workflow = workflow.with_job(job.with_saga_steps(completed.clone()));
+            completed.push(step);
+        }
+
+        workflow
+    }
+}
+
+impl Default for Saga {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Step of a saga that completed before the current job.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SagaStep {
+    pub id: String,
+    pub queue: String,
+    pub kind: String,
+    pub payload: Value,
+}
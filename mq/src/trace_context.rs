@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+use tracing::Span;
+
+use crate::Job;
+
+/// Header holding the W3C trace context of the span that published the job.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Captures the context of the current span in the headers with the global OpenTelemetry
+/// propagator unless they already carry one. Used by the backends when publishing jobs.
+#[cfg(feature = "opentelemetry")]
+pub fn inject_trace_context(headers: &mut BTreeMap<String, Value>) {
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    if headers.contains_key(TRACEPARENT_HEADER) {
+        return;
+    }
+
+    let context = Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+#[cfg(not(feature = "opentelemetry"))]
+pub fn inject_trace_context(_headers: &mut BTreeMap<String, Value>) {}
+
+/// Span wrapping the processing of a job, child of the trace context in its headers.
+pub(crate) fn job_span(job: &Job) -> Span {
+    let span = tracing::info_span!(
+        "job",
+        queue = job.queue(),
+        kind = job.kind(),
+        id = job.id(),
+        attempt = job.attempts(),
+    );
+
+    #[cfg(feature = "opentelemetry")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(job.headers()))
+        });
+        // fails only when no OpenTelemetry layer is installed
+        let _ = span.set_parent(parent);
+    }
+
+    span
+}
+
+#[cfg(feature = "opentelemetry")]
+struct HeaderInjector<'a>(&'a mut BTreeMap<String, Value>);
+
+#[cfg(feature = "opentelemetry")]
+impl opentelemetry::propagation::Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_owned(), Value::String(value));
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+struct HeaderExtractor<'a>(&'a BTreeMap<String, Value>);
+
+#[cfg(feature = "opentelemetry")]
+impl opentelemetry::propagation::Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(Value::as_str)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(String::as_str).collect()
+    }
+}
@@ -1,16 +1,34 @@
-use std::{future::ready, pin::pin, time::Duration};
+use std::{
+    future::ready,
+    pin::pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::{Consumer, Context, Error, JobProcessor};
-use futures::{stream, FutureExt, StreamExt, TryStreamExt};
-use serde_json::json;
+use crate::{
+    job_event::broadcast_stream,
+    job_metrics::{InFlight, JobMetric},
+    trace_context::job_span,
+    Consumer, Context, DependencyFailure, Error, Job, JobEvent, JobEventFilter, JobEventStream,
+    JobEventType, JobHandler, JobProcessor, Producer, SagaStep, WorkerHealth, WorkerHooks,
+    Workflow,
+};
+use futures::{stream, stream::BoxStream, FutureExt, StreamExt, TryStreamExt};
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, warn};
+use tracing::{debug, error, warn, Instrument};
 
 pub struct Worker {
     consumer: Consumer,
     cancellation_token: CancellationToken,
     concurrency: Option<usize>,
     poll_interval: Option<u64>,
+    queue_depth_interval: Option<u64>,
+    producer: Option<Arc<dyn Producer>>,
+    events: broadcast::Sender<JobEvent>,
+    hooks: Arc<dyn WorkerHooks>,
+    health: WorkerHealth,
 }
 
 impl Worker {
@@ -20,6 +38,11 @@ impl Worker {
             consumer,
             concurrency: None,
             poll_interval: Some(3000),
+            queue_depth_interval: None,
+            producer: None,
+            events: broadcast::channel(1024).0,
+            hooks: Arc::new(()),
+            health: WorkerHealth::default(),
         }
     }
 
@@ -41,6 +64,18 @@ impl Worker {
         self
     }
 
+    pub fn queue_depth_interval(&self) -> &Option<u64> {
+        &self.queue_depth_interval
+    }
+
+    /// Interval in milliseconds at which the depth of the queues of the worker is read with the
+    /// producer set with [`Worker::with_producer`] and recorded with [`crate::QueueDepth::record`].
+    /// Disabled by default.
+    pub fn with_queue_depth_interval(mut self, queue_depth_interval: Option<u64>) -> Self {
+        self.queue_depth_interval = queue_depth_interval;
+        self
+    }
+
     pub fn cancellation_token(&self) -> &CancellationToken {
         &self.cancellation_token
     }
@@ -50,6 +85,41 @@ impl Worker {
         self
     }
 
+    pub fn producer(&self) -> &Option<Arc<dyn Producer>> {
+        &self.producer
+    }
+
+    /// Producer used to publish jobs enqueued by handlers via [`Context::enqueue`].
+    pub fn with_producer(mut self, producer: Arc<dyn Producer>) -> Self {
+        self.producer = Some(producer);
+        self
+    }
+
+    pub fn hooks(&self) -> &Arc<dyn WorkerHooks> {
+        &self.hooks
+    }
+
+    /// Hooks invoked around each job processed by this worker.
+    pub fn with_hooks(mut self, hooks: impl WorkerHooks + 'static) -> Self {
+        self.hooks = Arc::new(hooks);
+        self
+    }
+
+    /// Health of the worker loop, e.g. to serve a readiness probe.
+    pub fn health(&self) -> &WorkerHealth {
+        &self.health
+    }
+
+    /// Subscribe to the lifecycle events of the jobs processed by this worker.
+    pub fn subscribe(&self, filter: JobEventFilter) -> JobEventStream {
+        broadcast_stream(self.events.subscribe(), filter)
+    }
+
+    fn emit(&self, queue: &str, kind: &str, id: &str, event: JobEventType) {
+        // no subscribers is not an error
+        let _ = self.events.send(JobEvent::new(queue, kind, id, event));
+    }
+
     pub async fn run(self, job_processor: impl JobProcessor) -> Result<(), Error> {
         let interval =
             tokio::time::interval(Duration::from_millis(self.poll_interval.unwrap_or(3000)));
@@ -58,19 +128,50 @@ impl Worker {
 
         let ct = pin!(self.cancellation_token.cancelled().fuse());
 
-        let job_stream = stream::unfold((interval, ct), |mut f| async {
+        let notifications = job_processor.job_notifications(&queues).await?;
+
+        let job_stream = stream::unfold((interval, ct, notifications), |mut f| async {
             tokio::select! {
-                 _ = (&mut f.0).tick() => Some((StreamSource::Polling, f)),
+                 _ = f.0.tick() => Some((StreamSource::Polling, f)),
+                Some(()) = next_notification(&mut f.2) => Some((StreamSource::Notification, f)),
                 _ = &mut f.1 => None,
             }
         });
 
-        job_stream
+        self.health.set_running(true);
+        let jobs = job_stream
             .then(|source| self.process_next_job(source, &job_processor, &queues))
-            .try_for_each_concurrent(self.concurrency, |_| ready(Ok(())))
-            .await?;
+            .try_for_each_concurrent(self.concurrency, |_| ready(Ok(())));
+        // sampling never completes, so the worker stops with the job stream
+        let result = tokio::select! {
+            result = jobs => result,
+            _ = self.sample_queue_depth(&queues) => Ok(()),
+        };
+        self.health.set_running(false);
 
-        Ok(())
+        result
+    }
+
+    /// Record the depth of the queues on every queue depth interval.
+    async fn sample_queue_depth(&self, queues: &[&str]) {
+        let (Some(interval), Some(producer)) = (self.queue_depth_interval, &self.producer) else {
+            return std::future::pending().await;
+        };
+        let mut interval = tokio::time::interval(Duration::from_millis(interval));
+
+        loop {
+            interval.tick().await;
+            for queue in queues {
+                match producer.queue_depth(queue).await {
+                    Ok(depth) => depth.record(queue),
+                    Err(Error::NotSupported(_)) => {
+                        warn!("queue_depth is not supported by the producer, not sampling it");
+                        return std::future::pending().await;
+                    }
+                    Err(e) => warn!("Failed to read the depth of queue={} with {:?}", queue, e),
+                }
+            }
+        }
     }
 
     async fn process_next_job<T: JobProcessor>(
@@ -79,8 +180,31 @@ impl Worker {
         job_processor: &T,
         queues: &[&str],
     ) -> Result<(), Error> {
+        // jobs whose last attempt ended by lease expiry were never failed by a worker
+        match job_processor.fail_expired_jobs(queues).await {
+            Ok(jobs) => {
+                for job in jobs {
+                    warn!(
+                        "Job queue={}, kind={}, id={} failed as its lease expired on its last attempt",
+                        job.queue(),
+                        job.kind(),
+                        job.id()
+                    );
+                    if !job.saga_steps().is_empty() {
+                        self.compensate(job.id(), job.saga_steps().to_vec()).await;
+                    }
+                }
+            }
+            Err(e) => error!("Failed to fail the jobs with expired leases with {:?}", e),
+        }
+
         loop {
-            match job_processor.poll_next_job(queues).await? {
+            let polled = Instant::now();
+            let next_job = job_processor.poll_next_job(queues).await;
+            metrics::histogram!(crate::POLL_DURATION).record(polled.elapsed().as_secs_f64());
+            self.health.set_last_poll_succeeded(next_job.is_ok());
+
+            match next_job? {
                 Some(job) => {
                     // TODO: Probably want to filter via queues+kind instead of just queue. But for now
                     // using queues so it is compatible with other backends.
@@ -93,49 +217,10 @@ impl Worker {
 
                     match handler {
                         Some(handler) => {
-                            let id = job.id().to_string();
-                            let ctx = Context::new(job, self.cancellation_token.clone());
-
-                            match handler.handle(ctx).await {
-                                Ok(result) => match result {
-                                    crate::JobResult::CompleteWithSuccess => {
-                                        job_processor
-                                            .complete_job_with_success(
-                                                handler.queue(),
-                                                handler.kind(),
-                                                &id,
-                                            )
-                                            .await?;
-                                    }
-                                    crate::JobResult::CompleteWithCancelled(message) => {
-                                        job_processor
-                                            .complete_job_with_cancelled(
-                                                handler.queue(),
-                                                handler.kind(),
-                                                &id,
-                                                message,
-                                            )
-                                            .await?;
-                                    }
-                                },
-                                Err(e) => {
-                                    error!(
-                                        "Job queue={}, kind={}, id={} failed with {:?}",
-                                        handler.queue(),
-                                        handler.kind(),
-                                        &id,
-                                        &e
-                                    );
-                                    job_processor
-                                        .fail_job(
-                                            handler.queue(),
-                                            handler.kind(),
-                                            &id,
-                                            json!({ "error": e.to_string() }),
-                                        )
-                                        .await?;
-                                }
-                            }
+                            let span = job_span(&job);
+                            self.process_job(job_processor, handler.as_ref(), job)
+                                .instrument(span)
+                                .await?;
                         }
                         None => {
                             warn!(
@@ -155,9 +240,228 @@ impl Worker {
 
         Ok(())
     }
+
+    async fn process_job<T: JobProcessor>(
+        &self,
+        job_processor: &T,
+        handler: &dyn JobHandler,
+        job: Job,
+    ) -> Result<(), Error> {
+        let id = job.id().to_string();
+        let attempt = job.attempts();
+        let exhausted = job.attempts() >= job.max_attempts();
+        let saga_steps = job.saga_steps().to_vec();
+        let metadata = job.clone();
+        let ctx =
+            Context::new(job, self.cancellation_token.clone()).with_producer(self.producer.clone());
+        let enqueued = ctx.enqueued();
+
+        self.emit(
+            handler.queue(),
+            handler.kind(),
+            &id,
+            JobEventType::Started { attempt },
+        );
+        let in_flight = InFlight::start(&metadata);
+        self.hooks.on_start(&metadata).await;
+        let started = Instant::now();
+
+        let result = handler.handle(ctx).await;
+        let duration = started.elapsed();
+
+        match result {
+            Ok(result) => match result {
+                crate::JobResult::CompleteWithSuccess => {
+                    let jobs = std::mem::take(&mut *enqueued.lock().unwrap());
+                    self.complete_job_with_success(job_processor, handler, &id, None, jobs)
+                        .await?;
+                    self.emit(
+                        handler.queue(),
+                        handler.kind(),
+                        &id,
+                        JobEventType::Succeeded,
+                    );
+                    in_flight.finish(duration, JobMetric::Succeeded);
+                    self.hooks.on_success(&metadata, duration).await;
+                }
+                crate::JobResult::CompleteWithOutput(output) => {
+                    let jobs = std::mem::take(&mut *enqueued.lock().unwrap());
+                    self.complete_job_with_success(job_processor, handler, &id, Some(output), jobs)
+                        .await?;
+                    self.emit(
+                        handler.queue(),
+                        handler.kind(),
+                        &id,
+                        JobEventType::Succeeded,
+                    );
+                    in_flight.finish(duration, JobMetric::Succeeded);
+                    self.hooks.on_success(&metadata, duration).await;
+                }
+                crate::JobResult::CompleteWithCancelled(message) => {
+                    job_processor
+                        .complete_job_with_cancelled(
+                            handler.queue(),
+                            handler.kind(),
+                            &id,
+                            message.clone(),
+                        )
+                        .await?;
+                    self.emit(
+                        handler.queue(),
+                        handler.kind(),
+                        &id,
+                        JobEventType::Cancelled {
+                            message: message.clone(),
+                        },
+                    );
+                    in_flight.finish(duration, JobMetric::Cancelled);
+                    self.hooks
+                        .on_cancelled(&metadata, duration, message.as_deref())
+                        .await;
+                }
+            },
+            Err(e) => {
+                in_flight.finish(duration, JobMetric::Failed { exhausted });
+                error!(
+                    "Job queue={}, kind={}, id={} failed with {:?}",
+                    handler.queue(),
+                    handler.kind(),
+                    &id,
+                    &e
+                );
+                let error = json!({ "error": e.to_string() });
+                job_processor
+                    .fail_job(handler.queue(), handler.kind(), &id, error.clone())
+                    .await?;
+                // only once the job is failed, otherwise its lease expires and it is compensated
+                // again by fail_expired_jobs
+                if exhausted && !saga_steps.is_empty() {
+                    self.compensate(&id, saga_steps).await;
+                }
+                self.emit(
+                    handler.queue(),
+                    handler.kind(),
+                    &id,
+                    JobEventType::Failed {
+                        attempt,
+                        error: error.clone(),
+                    },
+                );
+                self.hooks.on_failure(&metadata, duration, &e).await;
+                if exhausted {
+                    self.emit(
+                        handler.queue(),
+                        handler.kind(),
+                        &id,
+                        JobEventType::DeadLettered { error },
+                    );
+                    self.hooks.on_exhausted(&metadata, duration, &e).await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn complete_job_with_success<T: JobProcessor>(
+        &self,
+        job_processor: &T,
+        handler: &dyn JobHandler,
+        id: &str,
+        output: Option<Value>,
+        jobs: Vec<Job>,
+    ) -> Result<(), Error> {
+        match (&self.producer, jobs.is_empty(), output) {
+            (Some(producer), false, output) => {
+                job_processor
+                    .complete_job_with_success_and_publish(
+                        handler.queue(),
+                        handler.kind(),
+                        id,
+                        output,
+                        jobs,
+                        producer.as_ref(),
+                    )
+                    .await
+            }
+            // unreachable as Context::enqueue rejects the jobs without a producer
+            (None, false, _) => Err(Error::NotSupported(
+                "enqueue requires the worker to be configured with a producer".into(),
+            )),
+            (_, _, Some(output)) => {
+                job_processor
+                    .complete_job_with_output(handler.queue(), handler.kind(), id, output)
+                    .await
+            }
+            (_, _, None) => {
+                job_processor
+                    .complete_job_with_success(handler.queue(), handler.kind(), id)
+                    .await
+            }
+        }
+    }
+
+    /// Enqueue the compensations of the completed saga steps in reverse order. Errors are logged
+    /// rather than returned so the failed job is still failed.
+    async fn compensate(&self, id: &str, saga_steps: Vec<SagaStep>) {
+        let Some(producer) = &self.producer else {
+            warn!(
+                "Job id={} exhausted its attempts but no producer is configured to compensate its saga",
+                id
+            );
+            return;
+        };
+
+        let mut workflow = Workflow::new();
+        let mut previous: Option<String> = None;
+
+        for step in saga_steps.into_iter().rev() {
+            let Some(kind) = self.consumer.compensation(&step.queue, &step.kind) else {
+                debug!(
+                    "No compensation registered. queue={} kind={}",
+                    step.queue, step.kind
+                );
+                continue;
+            };
+
+            let job = Job::new(kind, step.payload).with_queue(step.queue);
+            let job = match previous.take() {
+                Some(previous) => job
+                    .with_depends_on([previous])
+                    .with_on_dependency_failure(DependencyFailure::Continue),
+                None => job,
+            };
+            previous = Some(job.id().to_owned());
+            workflow = workflow.with_job(job);
+        }
+
+        if let Err(e) = producer.publish_workflow(workflow).await {
+            error!(
+                "Failed to compensate the saga of job id={} with {:?}",
+                id, e
+            );
+        }
+    }
 }
 
 #[derive(Debug)]
 enum StreamSource {
     Polling,
+    Notification,
+}
+
+/// Waits for the next notification. Once the stream ended it is dropped and never polled again,
+/// so the worker only polls on its interval.
+async fn next_notification(notifications: &mut Option<BoxStream<'static, ()>>) -> Option<()> {
+    if let Some(stream) = notifications {
+        match stream.next().await {
+            Some(()) => return Some(()),
+            None => {
+                warn!("Job notifications ended, falling back to polling on the interval");
+                *notifications = None;
+            }
+        }
+    }
+
+    std::future::pending().await
 }
@@ -0,0 +1,36 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Health of a [`crate::Worker`], shared with the code reporting it.
+#[derive(Debug, Clone, Default)]
+pub struct WorkerHealth {
+    running: Arc<AtomicBool>,
+    last_poll_succeeded: Arc<AtomicBool>,
+}
+
+impl WorkerHealth {
+    /// Returns true while the worker loop is running.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Returns true when the last `poll_next_job` of the worker succeeded.
+    pub fn last_poll_succeeded(&self) -> bool {
+        self.last_poll_succeeded.load(Ordering::Relaxed)
+    }
+
+    /// Returns true when the worker is running and its last poll succeeded.
+    pub fn is_ready(&self) -> bool {
+        self.is_running() && self.last_poll_succeeded()
+    }
+
+    pub(crate) fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_last_poll_succeeded(&self, succeeded: bool) {
+        self.last_poll_succeeded.store(succeeded, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{Error, Job};
+
+/// Callbacks invoked by [`crate::Worker`] around each job it processes.
+///
+/// The job passed to the hooks is the polled job, so its attempts include the current attempt.
+/// All methods default to doing nothing.
+#[async_trait]
+pub trait WorkerHooks: Send + Sync {
+    /// Called before the handler runs.
+    async fn on_start(&self, _job: &Job) {}
+
+    /// Called once the job completed successfully.
+    async fn on_success(&self, _job: &Job, _duration: Duration) {}
+
+    /// Called once the job was cancelled by its handler.
+    async fn on_cancelled(&self, _job: &Job, _duration: Duration, _message: Option<&str>) {}
+
+    /// Called after every failed attempt.
+    async fn on_failure(&self, _job: &Job, _duration: Duration, _error: &Error) {}
+
+    /// Called after the last failed attempt, once [`WorkerHooks::on_failure`] returned.
+    async fn on_exhausted(&self, _job: &Job, _duration: Duration, _error: &Error) {}
+}
+
+impl WorkerHooks for () {}
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Job;
+
+/// Group of jobs published together where jobs can depend on other jobs of the workflow.
+///
+/// Jobs are published in the order they are added, so prerequisites must be added before their
+/// dependents.
+#[derive(Debug)]
+pub struct Workflow {
+    id: String,
+    jobs: Vec<Job>,
+}
+
+impl Workflow {
+    pub fn new() -> Self {
+        Self {
+            id: xid::new().to_string(),
+            jobs: Vec::new(),
+        }
+    }
+
+    pub fn with_id<S: Into<String>>(mut self, id: S) -> Self {
+        let id = id.into();
+        self.jobs = self
+            .jobs
+            .into_iter()
+            .map(|job| job.with_workflow_id(Some(id.clone())))
+            .collect();
+        self.id = id;
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn with_job(mut self, job: Job) -> Self {
+        self.jobs.push(job.with_workflow_id(Some(self.id.clone())));
+        self
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    pub fn into_jobs(self) -> Vec<Job> {
+        self.jobs
+    }
+}
+
+impl Default for Workflow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What happens to a job when one of its dependencies is cancelled or exhausts its attempts.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyFailure {
+    /// Cancel the job and, transitively, its own dependents.
+    #[default]
+    Cancel,
+    /// Treat the dependency as resolved and run the job anyway.
+    Continue,
+}
+
+impl DependencyFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DependencyFailure::Cancel => "cancel",
+            DependencyFailure::Continue => "continue",
+        }
+    }
+}
+
+/// Remaining jobs of a workflow. Completed and cancelled jobs are not counted.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkflowProgress {
+    /// Jobs waiting for their dependencies to complete.
+    pub waiting: u64,
+    /// Jobs ready to be polled, including the ones waiting to be retried.
+    pub ready: u64,
+    /// Jobs currently leased by a worker.
+    pub running: u64,
+    /// Jobs that exhausted their attempts.
+    pub failed: u64,
+}
+
+impl WorkflowProgress {
+    /// Returns true when no job of the workflow is left to run.
+    pub fn is_finished(&self) -> bool {
+        self.waiting == 0 && self.ready == 0 && self.running == 0
+    }
+}